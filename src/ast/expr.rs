@@ -1,15 +1,80 @@
-use super::token::Token;
+use std::cell::Cell;
 
+use super::{span::Span, token::Token};
+
+//Filled by the resolver. Number of scopes between the use of a variable and
+//the scope where it is declared. None means that the variable is global
+pub type Depth = Cell<Option<usize>>;
+
+//Every node knows the piece of source it was parsed from
 #[derive(PartialEq, Debug)]
-pub enum Expr {
+pub struct Expr {
+    pub kind: ExprKind,
+    pub span: Span,
+}
+
+impl Expr {
+    pub fn new(kind: ExprKind, span: Span) -> Self {
+        Self { kind, span }
+    }
+}
+
+//A chain of operators (ie. 1 + 1 + ... + 1) nests as deep as it is long.
+//The nodes are dropped in a loop, dropping them recursively could
+//overflow the stack
+impl Drop for Expr {
+    fn drop(&mut self) {
+        let mut pending = Vec::new();
+        self.kind.take_children(&mut pending);
+        while let Some(mut e) = pending.pop() {
+            e.kind.take_children(&mut pending);
+        }
+    }
+}
+
+#[derive(PartialEq, Debug)]
+pub enum ExprKind {
     Binary(Box<Expr>, Box<Token>, Box<Expr>), //left, operator, right
     Grouping(Box<Expr>),                      //expression
     NumberLit(f64),                           //value
     StringLit(Box<String>),                   //value
     Unary(Box<Token>, Box<Expr>),             //operator, right
-    Variable(Box<String>),                    //name | This is used when the variable is referenced
-    Assignment(Box<String>, Box<Expr>),       //name, value
+    Variable(Box<String>, Depth),             //name | This is used when the variable is referenced
+    Assignment(Box<String>, Box<Expr>, Depth), //name, value
+    CompoundAssignment(Box<String>, Box<Token>, Box<Expr>, Depth), //name, operator (+= -= *= /=), value
     Boolean(bool),
     Logical(Box<Expr>, Box<Token>, Box<Expr>), // expr && expr \ expr || expr
+    Conditional(Box<Expr>, Box<Expr>, Box<Expr>), //condition, then, else | cond ? a : b
+    Call(Box<Expr>, Box<Token>, Vec<Box<Expr>>), //callee, closing paren (For error reporting), arguments
+    Get(Box<Expr>, Box<Token>),                  //object, property name
+    Set(Box<Expr>, Box<Token>, Box<Expr>),       //object, property name, value
+    CompoundSet(Box<Expr>, Box<Token>, Box<Token>, Box<Expr>), //object, property name, operator, value
+    This(Box<Token>, Depth), //this keyword | Resolved as any other variable
+    Super(Box<Token>, Box<Token>, Depth), //super keyword, method name | Resolved as the variable super
     Nil,
 }
+
+impl ExprKind {
+    //Moves the inner expressions out, the node is left as nil
+    fn take_children(&mut self, out: &mut Vec<Expr>) {
+        match std::mem::replace(self, ExprKind::Nil) {
+            ExprKind::Binary(left, _, right)
+            | ExprKind::Logical(left, _, right)
+            | ExprKind::Set(left, _, right)
+            | ExprKind::CompoundSet(left, _, _, right) => out.extend([*left, *right]),
+            ExprKind::Grouping(e)
+            | ExprKind::Unary(_, e)
+            | ExprKind::Assignment(_, e, _)
+            | ExprKind::CompoundAssignment(_, _, e, _)
+            | ExprKind::Get(e, _) => out.push(*e),
+            ExprKind::Conditional(condition, then, else_) => {
+                out.extend([*condition, *then, *else_])
+            }
+            ExprKind::Call(callee, _, arguments) => {
+                out.push(*callee);
+                out.extend(arguments.into_iter().map(|a| *a));
+            }
+            _ => {}
+        }
+    }
+}
@@ -3,6 +3,7 @@ mod integration_test;
 pub mod parser;
 pub mod printer;
 pub mod scanner;
+pub mod span;
 pub mod stmt;
 pub mod token;
 pub trait ExprVisitor<T> {
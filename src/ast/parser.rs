@@ -3,10 +3,22 @@
 
 *       program        → declaration* EOF ;
 
-*       declaration    → varDecl          -> This is a kind of stmt
+*       declaration    → classDecl
+*                      | funDecl
+*                      | varDecl          -> This is a kind of stmt
+*                      | constDecl
 *                      | statement ;
 
-*       varDecl        → "var" IDENTIFIER ( "=" expression )? ";" ;
+*       classDecl      → "class" IDENTIFIER ( "<" IDENTIFIER )? "{" function* "}" ;
+
+*       funDecl        → "fun" function ;
+*       function       → IDENTIFIER "(" parameters? ")" ( ":" type )? blockStmt ;
+*       parameters     → IDENTIFIER ( ":" type )? ( "," IDENTIFIER ( ":" type )? )* ;
+
+*       varDecl        → "var" IDENTIFIER ( ":" type )? ( "=" expression )? ";" ;
+*       constDecl      → "const" IDENTIFIER ( ":" type )? "=" expression ";" ;
+
+*       type           → IDENTIFIER | "nil" ;   -> Checked by the type checker, not here
 
 *   ==================== STMTs ====================
 
@@ -15,56 +27,56 @@
 *                      | printStmt     | Match the option
 *                      | whileStmt     |
 *                      | forStmt       |
+*                      | returnStmt    |
 *                      | blockStmt    ;-
 
 
 *       forStmt        → "for" "(" ( varDecl | exprStmt | ";" )
 *                        expression? ";"
-*                        expression? ")" blockStmt ;
+*                        expression? ")" statement ;
 
-*       whileStmt      → "while" "(" expression ")" blockStmt ;
+*       whileStmt      → "while" "(" expression ")" statement ;
 
 *       printStmt      → "print" expression ";" ;
 
+*       returnStmt     → "return" expression? ";" ;
+
 *       exprStmt       → expression ";" ;
 
 *       blockStmt      → "{" declaration* "}" ";" In fact it is kind of a subprogram. But this notation seems more clear
 
-        TODO: Add support of if-else
-*       ifStmt         → "if" "(" expression ")" blockStmt ("else" blockStmt)?
+*       ifStmt         → "if" "(" expression ")" statement
+*                        ( "else" statement )? ;   -> The else goes with the closest if
 
 *   ==================== EXPRs ====================
 
 *		expression     → assignment ;
 
-*       assignment     → IDENTIFIER "=" assignment
-*                      | logic_or ;
+*       assignment     → ( call "." )? IDENTIFIER ( "=" | "+=" | "-=" | "*=" | "/=" ) assignment
+*                      | conditional ;
 *
-*       logic_or       → logic_and ("or" logic_and)* ;
+*       conditional    → logic_or ( "?" expression ":" conditional )? ;
 *
-*       logic_and      → equality ("and" equality)* ;
+*       The binary levels are not parsed rule by rule. All of them are
+*       handled by binary_rule, using the precedence in BINARY_OPERATORS.
+*       Every level is left associative
 *
+*       logic_or       → logic_and ("or" logic_and)* ;
+*       logic_and      → equality ("and" equality)* ;
 *       equality       → comparison ( ( "!=" | "==" ) comparison )* ;
-*                                     '-------------' -> Match
-*                                   '-----------------------------' -> While || Undf
-*
 *		comparison     → term ( ( ">" | ">=" | "<" | "<=" ) term )* ;
-*                               '-------------------------' -> Match
-*                             '----------------------------------' -> While || Undf
-
 *		term           → factor ( ( "-" | "+" ) factor )* ;
-*                                 '-----------' -> Match
-*                               '----------------------' -> While || Undf
-*
 *		factor         → unary ( ( "/" | "*" ) unary )* ;
-*                                '-----------' -> Match
-*                             '----------------------------------' -> While || Undf
+*
 *		unary          → ( "!" | "-" ) unary    -
 *                        '-----------' -> Match | -> Match entre ambos
-*		               | primary ;              -
+*		               | call ;                 -
 *
+*       call           → primary ( "(" arguments? ")" | "." IDENTIFIER )* ;
+*       arguments      → expression ( "," expression )* ;
 *
-*		primary        → NUMBER | STRING | "true" | "false" | "nil"
+*		primary        → NUMBER | STRING | "true" | "false" | "nil" | "this"
+*		               | IDENTIFIER | "super" "." IDENTIFIER
 *		               | "(" expression ")" ;
 
 *   Que surge naturalmente de esto -> Una funcion peek que devuelva Optional<Expr>
@@ -74,57 +86,238 @@
 
 use std::fmt::Display;
 
+use std::rc::Rc;
+
+use crate::stack::StackGuard;
+
 use super::{
-    expr::Expr,
-    stmt::Stmt,
+    expr::{Depth, Expr, ExprKind},
+    span::Span,
+    stmt::{FunctionDecl, Stmt, StmtKind},
     token::{Token, TokenType},
 };
 
-#[derive(Debug)]
+//Every error keeps the span of the token (or expression) that caused it
+#[derive(Debug, PartialEq)]
 pub enum ParserError {
-    UnexpectedToken(String),
-    NonValidAssigmentTarget,
+    UnexpectedToken(String, Span),
+    NonValidAssigmentTarget(Span),
+    TooManyArguments(Span),
+    TooDeeplyNested(Span),
 }
 
-impl Display for ParserError {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+impl ParserError {
+    pub fn span(&self) -> Span {
         match self {
-            Self::UnexpectedToken(e) => {
-                write!(f, "[Error] - Parsing error: Unexpected token - {}", e)
-            }
-            Self::NonValidAssigmentTarget => {
-                write!(f, "[Error] - Parsing error: Non valid assigment target")
+            Self::UnexpectedToken(_, span)
+            | Self::NonValidAssigmentTarget(span)
+            | Self::TooManyArguments(span)
+            | Self::TooDeeplyNested(span) => *span,
+        }
+    }
+
+    //The description alone, without the location
+    pub fn message(&self) -> String {
+        match self {
+            Self::UnexpectedToken(e, _) => format!("Unexpected token - {}", e),
+            Self::NonValidAssigmentTarget(_) => String::from("Non valid assigment target"),
+            Self::TooManyArguments(_) => {
+                format!("Can't have more than {} arguments", MAX_ARGUMENTS)
             }
+            Self::TooDeeplyNested(_) => String::from("Nested too deeply to fit in the stack"),
         }
     }
 }
 
+impl Display for ParserError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let span = self.span();
+        write!(
+            f,
+            "[Error] - Parsing error at {}:{}: {}",
+            span.line,
+            span.column,
+            self.message()
+        )
+    }
+}
+
+//Same limit as the reference implementation
+const MAX_ARGUMENTS: usize = 255;
+
+//Returned as the current token type once every token has been consumed
+static EOF_TYPE: TokenType = TokenType::EOF;
+
 pub type ExprParserResult = Result<Expr, ParserError>;
 pub type StmtParserResult = Result<Stmt, ParserError>;
 
+//How tight each binary operator binds, from the loosest to the tightest
+#[derive(Debug, PartialEq, PartialOrd, Clone, Copy)]
+pub enum Precedence {
+    Or,
+    And,
+    Equality,
+    Comparison,
+    Term,
+    Factor,
+    //Tighter than any binary operator, the right operand takes none of them
+    Unary,
+}
+
+impl Precedence {
+    //The right operand of a left associative operator starts one level above
+    fn next(self) -> Self {
+        match self {
+            Self::Or => Self::And,
+            Self::And => Self::Equality,
+            Self::Equality => Self::Comparison,
+            Self::Comparison => Self::Term,
+            Self::Term => Self::Factor,
+            Self::Factor | Self::Unary => Self::Unary,
+        }
+    }
+}
+
+struct BinaryOperator {
+    token_type: TokenType,
+    precedence: Precedence,
+    //and / or short circuit, so they are evaluated as a different node
+    logical: bool,
+}
+
+//Every binary operator of the language. Adding a new one only needs an
+//entry here (and its evaluation in the interpreter)
+const BINARY_OPERATORS: [BinaryOperator; 12] = [
+    BinaryOperator {
+        token_type: TokenType::OR,
+        precedence: Precedence::Or,
+        logical: true,
+    },
+    BinaryOperator {
+        token_type: TokenType::AND,
+        precedence: Precedence::And,
+        logical: true,
+    },
+    BinaryOperator {
+        token_type: TokenType::BANGEQUAL,
+        precedence: Precedence::Equality,
+        logical: false,
+    },
+    BinaryOperator {
+        token_type: TokenType::EQUALEQUAL,
+        precedence: Precedence::Equality,
+        logical: false,
+    },
+    BinaryOperator {
+        token_type: TokenType::GREATER,
+        precedence: Precedence::Comparison,
+        logical: false,
+    },
+    BinaryOperator {
+        token_type: TokenType::GREATEREQUAL,
+        precedence: Precedence::Comparison,
+        logical: false,
+    },
+    BinaryOperator {
+        token_type: TokenType::LESS,
+        precedence: Precedence::Comparison,
+        logical: false,
+    },
+    BinaryOperator {
+        token_type: TokenType::LESSEQUAL,
+        precedence: Precedence::Comparison,
+        logical: false,
+    },
+    BinaryOperator {
+        token_type: TokenType::MINUS,
+        precedence: Precedence::Term,
+        logical: false,
+    },
+    BinaryOperator {
+        token_type: TokenType::PLUS,
+        precedence: Precedence::Term,
+        logical: false,
+    },
+    BinaryOperator {
+        token_type: TokenType::SLASH,
+        precedence: Precedence::Factor,
+        logical: false,
+    },
+    BinaryOperator {
+        token_type: TokenType::STAR,
+        precedence: Precedence::Factor,
+        logical: false,
+    },
+];
+
+fn binary_operator(token: &Token) -> Option<&'static BinaryOperator> {
+    BINARY_OPERATORS
+        .iter()
+        .find(|op| op.token_type == token.token_type)
+}
+
+//Binary and logical nodes span from the left operand to the right one
+fn binary_node<F>(kind: F, left: Expr, operator: Token, right: Expr) -> Expr
+where
+    F: Fn(Box<Expr>, Box<Token>, Box<Expr>) -> ExprKind,
+{
+    let span = left.span.merge(&right.span);
+    Expr::new(
+        kind(Box::new(left), Box::new(operator), Box::new(right)),
+        span,
+    )
+}
+
 pub struct Parser<'a> {
     tokens: &'a Vec<Token>,
     current: usize,
+    errors: Vec<ParserError>,
+    //The nested declarations and expressions are parsed recursively
+    stack: StackGuard,
 }
 
 //Public API and util methods
 impl<'a> Parser<'a> {
     pub fn new(tokens: &'a Vec<Token>) -> Self {
-        Self { tokens, current: 0 }
+        Self {
+            tokens,
+            current: 0,
+            errors: Vec::new(),
+            stack: StackGuard::default(),
+        }
     }
-    pub fn parse(&mut self) -> Result<Vec<Stmt>, ParserError> {
+
+    //Size of the stack of the thread that runs the parser. The deepest
+    //nesting allowed depends on it
+    pub fn set_stack_size(&mut self, stack_size: usize) {
+        self.stack = StackGuard::new(stack_size);
+    }
+
+    //Fails if there is any syntax error. Every error in the source is returned
+    pub fn parse(&mut self) -> Result<Vec<Stmt>, Vec<ParserError>> {
+        let (stmts, errors) = self.parse_partial();
+        match errors.is_empty() {
+            true => Ok(stmts),
+            false => Err(errors),
+        }
+    }
+
+    //Does not stop on errors. The statements that could not be parsed are
+    //left out of the returned AST, and the errors are returned along with it
+    pub fn parse_partial(&mut self) -> (Vec<Stmt>, Vec<ParserError>) {
+        self.stack.start();
         let mut stmts: Vec<Stmt> = Vec::new();
         while !self.is_at_end() {
-            //TODO: !HANDLE ERROR TO AVOID PANIC ON FIRST ERROR
-            let stmt = self.declaration()?;
-            stmts.push(stmt);
+            if let Some(stmt) = self.declaration() {
+                stmts.push(stmt);
+            }
         }
 
-        Ok(stmts)
+        (stmts, std::mem::take(&mut self.errors))
     }
 
     pub fn previous(&mut self) -> Option<&Token> {
-        self.tokens.get(self.current - 1)
+        self.tokens.get(self.current.checked_sub(1)?)
     }
 
     pub fn get_current_and_advance(&mut self) -> Option<&Token> {
@@ -132,9 +325,17 @@ impl<'a> Parser<'a> {
         self.previous()
     }
 
+    //Same as get_current_and_advance, but running out of tokens is an error
+    fn advance_cloned(&mut self) -> Result<Token, ParserError> {
+        match self.get_current_and_advance() {
+            Some(t) => Ok(t.clone()),
+            None => Err(self.unexpected(String::from("Unexpected EOF"))),
+        }
+    }
+
     //Todo: Set the error inside this.
     pub fn consume(&mut self, tt: TokenType) -> bool {
-        if tt == self.current_token().unwrap().token_type {
+        if tt == *self.current_type() {
             self.advance();
             return true;
         }
@@ -146,19 +347,15 @@ impl<'a> Parser<'a> {
         //This functions is used to check that the token has some specific type, the
         //internal lexeme for the token is ingored. Therefore, there is no check for equal value inside
         //token types (And is necessary this weird looking function istead of the built in == )
-        let curr = self.current_token().unwrap().token_type.clone();
-
-        if tt.weak_comparison(&curr) {
+        if tt.weak_comparison(self.current_type()) && self.current_token().is_some() {
             self.advance();
-            let curr = Ok(self.previous().unwrap());
-            return curr;
+            return self.previous().ok_or(ParserError::UnexpectedToken(
+                String::from("Unexpected EOF"),
+                Span::default(),
+            ));
         }
 
-        Err(ParserError::UnexpectedToken(format!(
-            "Expected {:?}, got {:?}",
-            tt,
-            self.current_token().unwrap()
-        )))
+        Err(self.unexpected(format!("Expected {:?}, got {:?}", tt, self.current_type())))
     }
 
     pub fn is_at_end(&self) -> bool {
@@ -183,80 +380,279 @@ impl<'a> Parser<'a> {
         self.tokens.get(self.current)
     }
 
+    //Past the last token this is always EOF, so the rules can match on it
+    //without checking for the end first
+    fn current_type(&self) -> &TokenType {
+        match self.current_token() {
+            Some(t) => &t.token_type,
+            None => &EOF_TYPE,
+        }
+    }
+
+    //Never moves more than one past the last token
     pub fn advance(&mut self) -> Option<&Token> {
-        self.current += 1;
+        if self.current < self.tokens.len() {
+            self.current += 1;
+        }
         self.tokens.get(self.current)
     }
+
+    //Span of the current token. Past the end, the last one is used
+    fn current_span(&self) -> Span {
+        match self.current_token().or(self.tokens.last()) {
+            Some(t) => t.span,
+            None => Span::default(),
+        }
+    }
+
+    //From the received start until the last consumed token
+    fn span_since(&self, start: Span) -> Span {
+        match self.current.checked_sub(1).and_then(|i| self.tokens.get(i)) {
+            Some(t) => start.merge(&t.span),
+            None => start,
+        }
+    }
+
+    fn unexpected(&self, message: String) -> ParserError {
+        ParserError::UnexpectedToken(message, self.current_span())
+    }
+
+    //Runs a rule one level deeper, failing when it would not fit in the stack
+    fn nested<T>(
+        &mut self,
+        rule: impl FnOnce(&mut Self) -> Result<T, ParserError>,
+    ) -> Result<T, ParserError> {
+        if !self.stack.has_room() {
+            return Err(ParserError::TooDeeplyNested(self.current_span()));
+        }
+        rule(self)
+    }
 }
 
 //Stmt parsing
 impl<'a> Parser<'a> {
-    pub fn declaration(&mut self) -> StmtParserResult {
-        let curr_tkn = self.current_token();
-        if curr_tkn.is_none() {
-            return Err(ParserError::UnexpectedToken(String::from(
-                "Non expected EOF",
-            )));
+    //Panic mode. When a declaration fails, the error is stored and every
+    //token until the start of the next statement is skipped, so the
+    //following errors are not caused by the first one
+    pub fn declaration(&mut self) -> Option<Stmt> {
+        let start = self.current;
+        match self.declaration_rule() {
+            Ok(stmt) => Some(stmt),
+            Err(e) => {
+                self.errors.push(e);
+                //Something must be skipped, or the same error would be found forever
+                if self.current == start && !self.is_at_end() {
+                    self.advance();
+                }
+                self.synchronize();
+                None
+            }
         }
+    }
 
-        match curr_tkn.unwrap().token_type {
-            TokenType::VAR => self.var_declaration(),
-            _ => self.parse_stmt(),
+    //A statement ends after a ; or just before a keyword that starts a new
+    //one. A } is not skipped either, it closes the block being parsed
+    fn synchronize(&mut self) {
+        while !self.is_at_end() {
+            if self.previous().map(|t| &t.token_type) == Some(&TokenType::SEMICOLON) {
+                return;
+            }
+            if matches!(
+                self.current_type(),
+                TokenType::RIGHTBRACE
+                    | TokenType::CLASS
+                    | TokenType::FUN
+                    | TokenType::VAR
+                    | TokenType::CONST
+                    | TokenType::FOR
+                    | TokenType::IF
+                    | TokenType::WHILE
+                    | TokenType::PRINT
+                    | TokenType::RETURN
+            ) {
+                return;
+            }
+            self.advance();
         }
     }
 
-    pub fn parse_stmt(&mut self) -> StmtParserResult {
-        let curr_tkn = self.current_token();
-        if curr_tkn.is_none() {
-            return Err(ParserError::UnexpectedToken(String::from(
-                "Non expected EOF",
+    fn declaration_rule(&mut self) -> StmtParserResult {
+        match self.current_type() {
+            TokenType::VAR => self.nested(Self::var_declaration),
+            TokenType::CONST => self.nested(Self::const_declaration),
+            TokenType::FUN => self.nested(Self::fun_declaration),
+            TokenType::CLASS => self.nested(Self::class_declaration),
+            _ => self.nested(Self::parse_stmt),
+        }
+    }
+
+    //classDecl      → "class" IDENTIFIER ( "<" IDENTIFIER )? "{" function* "}" ;
+    fn class_declaration(&mut self) -> StmtParserResult {
+        let start = self.current_span();
+        //Jump the class
+        self.advance();
+        let name = self
+            .consume_advance_return(TokenType::IDENTIFIER("".to_string()))?
+            .clone();
+
+        //The superclass is stored as a variable access, it is looked up at runtime
+        let mut superclass: Option<Box<Expr>> = None;
+        if self.consume(TokenType::LESS) {
+            let superclass_name = self
+                .consume_advance_return(TokenType::IDENTIFIER("".to_string()))?
+                .clone();
+            superclass = Some(Box::new(Expr::new(
+                ExprKind::Variable(Box::new(superclass_name.lexeme), Depth::default()),
+                superclass_name.span,
             )));
         }
+        self.consume_advance_return(TokenType::LEFTBRACE)?;
+
+        //Methods are declared as functions, without the fun keyword
+        let mut methods: Vec<Rc<FunctionDecl>> = Vec::new();
+        while !self.consume(TokenType::RIGHTBRACE) {
+            if self.is_at_end() {
+                return Err(self.unexpected(String::from(
+                    "Expected RIGHTBRACE after class body, got EOF",
+                )));
+            }
+            methods.push(self.function()?);
+        }
 
-        match curr_tkn.unwrap().token_type {
+        Ok(Stmt::new(
+            StmtKind::CLASS(Box::new(name), superclass, methods),
+            self.span_since(start),
+        ))
+    }
+
+    //funDecl        → "fun" function ;
+    fn fun_declaration(&mut self) -> StmtParserResult {
+        let start = self.current_span();
+        //Jump the fun
+        self.advance();
+        let function = self.function()?;
+        Ok(Stmt::new(
+            StmtKind::FUNCTION(function),
+            self.span_since(start),
+        ))
+    }
+
+    //function       → IDENTIFIER "(" parameters? ")" ( ":" type )? blockStmt ;
+    fn function(&mut self) -> Result<Rc<FunctionDecl>, ParserError> {
+        let name = self
+            .consume_advance_return(TokenType::IDENTIFIER("".to_string()))?
+            .clone();
+        self.consume_advance_return(TokenType::LEFTPAREN)?;
+
+        let mut params: Vec<Token> = Vec::new();
+        let mut param_types: Vec<Option<Token>> = Vec::new();
+        if !self.consume(TokenType::RIGHTPAREN) {
+            loop {
+                if params.len() >= MAX_ARGUMENTS {
+                    return Err(ParserError::TooManyArguments(self.current_span()));
+                }
+                let param = self
+                    .consume_advance_return(TokenType::IDENTIFIER("".to_string()))?
+                    .clone();
+                params.push(param);
+                param_types.push(self.type_annotation()?);
+                if !self.consume(TokenType::COMMA) {
+                    break;
+                }
+            }
+            self.consume_advance_return(TokenType::RIGHTPAREN)?;
+        }
+        let return_type = self.type_annotation()?;
+
+        //Be sure that the body is a block
+        self.expect_block()?;
+        let body = self.block_content()?;
+        let close = self.previous().map(|t| t.span).unwrap_or_default();
+
+        Ok(Rc::new(FunctionDecl {
+            name,
+            params,
+            param_types,
+            return_type,
+            body,
+            close,
+        }))
+    }
+
+    //( ":" type )?
+    //nil is a keyword, every other type name is an identifier
+    fn type_annotation(&mut self) -> Result<Option<Token>, ParserError> {
+        if !self.consume(TokenType::COLON) {
+            return Ok(None);
+        }
+        match self.current_type() {
+            TokenType::NIL => Ok(Some(self.advance_cloned()?)),
+            _ => Ok(Some(
+                self.consume_advance_return(TokenType::IDENTIFIER("".to_string()))?
+                    .clone(),
+            )),
+        }
+    }
+
+    pub fn parse_stmt(&mut self) -> StmtParserResult {
+        match self.current_type() {
             TokenType::PRINT => self.print_stmt(),
             TokenType::LEFTBRACE => self.block_stmt(),
             TokenType::IF => self.if_stmt(),
             TokenType::WHILE => self.while_stmt(),
             TokenType::FOR => self.for_stmt(),
+            TokenType::RETURN => self.return_stmt(),
             _ => self.expr_stmt(),
         }
     }
 
-    //whileStmt  → "while" "(" expression ")" block ;
+    //returnStmt     → "return" expression? ";" ;
+    fn return_stmt(&mut self) -> StmtParserResult {
+        let keyword = self.advance_cloned()?;
+
+        let mut value: Option<Box<Expr>> = None;
+        if !self.consume(TokenType::SEMICOLON) {
+            value = Some(Box::new(self.expr_rule()?));
+            self.consume_advance_return(TokenType::SEMICOLON)?;
+        }
+        let span = self.span_since(keyword.span);
+        Ok(Stmt::new(StmtKind::RETURN(Box::new(keyword), value), span))
+    }
+
+    //whileStmt  → "while" "(" expression ")" statement ;
     fn while_stmt(&mut self) -> StmtParserResult {
-        // "while" "(" expression ")" block
+        // "while" "(" expression ")" statement
         //   |      |   derive     |  derive
         //Start here|              |
         //         Must           Must
 
+        let start = self.current_span();
         self.advance();
         self.consume_advance_return(TokenType::LEFTPAREN)?;
         let condition = self.expr_rule()?;
         self.consume_advance_return(TokenType::RIGHTPAREN)?;
-        let curr = self.current_token().unwrap();
-        if curr.token_type != TokenType::LEFTBRACE {
-            return Err(ParserError::UnexpectedToken(format!(
-                "Expected {:?}, got {:?}",
-                curr.token_type, curr
-            )));
-        }
 
-        //The block will handle the closing }
-        let main_block = self.block_stmt()?;
-        Ok(Stmt::WHILE(Box::new(condition), Box::new(main_block)))
+        let main_block = self.body()?;
+        Ok(Stmt::new(
+            StmtKind::WHILE(Box::new(condition), Box::new(main_block)),
+            self.span_since(start),
+        ))
     }
 
     fn for_stmt(&mut self) -> StmtParserResult {
         //            |        Initializer       | condition    |  increment  |
-        //  "for" "(" ( varDecl | exprStmt | ";" ) expression? ";" expression? ")" blockStmt ;
+        //  "for" "(" ( varDecl | exprStmt | ";" ) expression? ";" expression? ")" statement ;
+        let start = self.current_span();
         self.advance();
 
         //If there is a (, give me the variable initializer first tkn
         self.consume_advance_return(TokenType::LEFTPAREN)?;
 
-        let initializer = match self.current_token().unwrap().token_type {
-            TokenType::SEMICOLON => None,
+        let initializer = match self.current_type() {
+            TokenType::SEMICOLON => {
+                self.advance();
+                None
+            }
             //Both var declaration and expr stmt handle the correpsonding closing ";"
             TokenType::VAR => Some(self.var_declaration()?),
             _ => Some(self.expr_stmt()?),
@@ -283,15 +679,9 @@ impl<'a> Parser<'a> {
             self.consume_advance_return(TokenType::RIGHTPAREN)?;
         }
 
-        //If there is no {, there is no body => Syntax errors
-        let curr = self.current_token().unwrap();
-        if curr.token_type != TokenType::LEFTBRACE {
-            return Err(ParserError::UnexpectedToken(format!(
-                "Expected {:?}, got {:?}",
-                curr.token_type, curr
-            )));
-        }
-        let body = self.block_stmt()?;
+        let body = self.body()?;
+        //The desugared nodes dont exist in the source, they take the span of the whole for
+        let span = self.span_since(start);
 
         //Note: Yes i know, all this _desugarization_ should be another
         //process, but thats for another day
@@ -314,9 +704,13 @@ impl<'a> Parser<'a> {
         //Build a block containing both the original body and the new increment
         let mut block_vec = vec![Box::new(body)];
         if let Some(e) = increment {
-            block_vec.push(Box::new(Stmt::EXPR(Box::new(e))));
+            let increment_span = e.span;
+            block_vec.push(Box::new(Stmt::new(
+                StmtKind::EXPR(Box::new(e)),
+                increment_span,
+            )));
         }
-        let new_body = Box::new(Stmt::BLOCK(block_vec));
+        let new_body = Box::new(Stmt::new(StmtKind::BLOCK(block_vec), span));
 
         //Now we will handle the condition.
         //In the case of for(Expr?; None; Expr?), then is the same as for(Expr?;true;Expr?)
@@ -324,11 +718,11 @@ impl<'a> Parser<'a> {
 
         let new_condition = Box::new(match cond {
             Some(c) => c,
-            None => Expr::Boolean(true),
+            None => Expr::new(ExprKind::Boolean(true), span),
         });
 
         //Lets build the While
-        let while_stmt = Stmt::WHILE(new_condition, new_body);
+        let while_stmt = Stmt::new(StmtKind::WHILE(new_condition, new_body), span);
         let mut whole_block_stmts: Vec<Box<Stmt>> = Vec::new();
 
         //If there is some initialization, add it before the while stmt
@@ -337,53 +731,79 @@ impl<'a> Parser<'a> {
         }
         whole_block_stmts.push(Box::new(while_stmt));
         //Well... now we have a new block with an internal while stmt
-        Ok(Stmt::BLOCK(whole_block_stmts))
+        Ok(Stmt::new(StmtKind::BLOCK(whole_block_stmts), span))
+    }
+
+    //The body of if, while and for can be any statement, but not a declaration
+    fn body(&mut self) -> StmtParserResult {
+        self.nested(Self::parse_stmt)
+    }
+
+    //Function bodies are blocks. The { is left for block_content
+    fn expect_block(&mut self) -> Result<(), ParserError> {
+        match self.current_type() {
+            TokenType::LEFTBRACE => Ok(()),
+            tt => Err(self.unexpected(format!("Expected LEFTBRACE, got {:?}", tt))),
+        }
     }
 
     fn block_stmt(&mut self) -> StmtParserResult {
+        let start = self.current_span();
+        let stmts = self.block_content()?;
+        Ok(Stmt::new(StmtKind::BLOCK(stmts), self.span_since(start)))
+    }
+
+    //Parses the declarations between { and }. Function bodies use it
+    //directly, because they dont need the extra BLOCK wrapper
+    fn block_content(&mut self) -> Result<Vec<Box<Stmt>>, ParserError> {
         //Consume the starting {
         self.advance();
         let mut internal_stmts: Vec<Box<Stmt>> = Vec::new();
 
-        while !self.consume(TokenType::RIGHTBRACE) && !self.is_at_end() {
-            let x = self.declaration()?;
-            internal_stmts.push(Box::new(x));
+        while !self.consume(TokenType::RIGHTBRACE) {
+            if self.is_at_end() {
+                return Err(self.unexpected(format!(
+                    "Expected RIGHTBRACE, got {:?}",
+                    self.current_type()
+                )));
+            }
+            if let Some(x) = self.declaration() {
+                internal_stmts.push(Box::new(x));
+            }
         }
 
-        //  The compiler complains about this code. It insists that is unreachable
-        //  im not sure about that, but im not that smart either
-
-        // if !self.consume(TokenType::RIGHTBRACE) {
-        //     return return Err(ParserError::UnexpectedToken(format!(
-        //         "Expected RIGHTBRACE, got {:?}",
-        //         self.current_token().unwrap()
-        //     )));
-
-        Ok(Stmt::BLOCK(internal_stmts))
+        Ok(internal_stmts)
     }
 
     fn print_stmt(&mut self) -> StmtParserResult {
+        let start = self.current_span();
         self.advance();
         let expr = self.expr_rule()?;
         if !self.consume(TokenType::SEMICOLON) {
-            return Err(ParserError::UnexpectedToken(format!(
-                "Expected SEMICOLON, got {:?}",
-                self.current_token().unwrap()
-            )));
+            return Err(
+                self.unexpected(format!("Expected SEMICOLON, got {:?}", self.current_type()))
+            );
         }
-        Ok(Stmt::PRINT(Box::new(expr)))
+        Ok(Stmt::new(
+            StmtKind::PRINT(Box::new(expr)),
+            self.span_since(start),
+        ))
     }
 
     //exprStmt       → expression ";" ;
     fn expr_stmt(&mut self) -> StmtParserResult {
+        let start = self.current_span();
         let expr = self.expr_rule()?;
         if !self.consume(TokenType::SEMICOLON) {
-            return Err(ParserError::UnexpectedToken(format!(
+            return Err(self.unexpected(format!(
                 "Expected SEMICOLON (;), got {:?}",
-                self.current_token().unwrap()
+                self.current_type()
             )));
         }
-        Ok(Stmt::EXPR(Box::new(expr)))
+        Ok(Stmt::new(
+            StmtKind::EXPR(Box::new(expr)),
+            self.span_since(start),
+        ))
     }
 
     fn var_declaration(&mut self) -> StmtParserResult {
@@ -391,66 +811,98 @@ impl<'a> Parser<'a> {
         //                    |         |       |            |         |
         //                Start here -> Jump (-> Check  ->  Build)    MUST BE
         //                                   |-----Optional------|
+        let start = self.current_span();
         if !self.consume(TokenType::VAR) {
-            return Err(ParserError::UnexpectedToken(format!(
-                "Expected VAR, got {:?}",
-                self.current_token().unwrap().token_type
-            )));
+            return Err(self.unexpected(format!("Expected VAR, got {:?}", self.current_type())));
         }
         //There must be a name
 
         //# This clone is ugly but works
         let name = self
-            .consume_advance_return(TokenType::IDENTIFIER("".to_string()))
-            .cloned();
+            .consume_advance_return(TokenType::IDENTIFIER("".to_string()))?
+            .clone();
+        let type_ = self.type_annotation()?.map(Box::new);
 
         let mut initializer: Option<Box<Expr>> = None;
         if self.consume(TokenType::EQUAL) {
-            initializer = Some(Box::new(self.expr_rule().unwrap()));
+            initializer = Some(Box::new(self.expr_rule()?));
         }
 
         if !self.consume(TokenType::SEMICOLON) {
-            return Err(ParserError::UnexpectedToken(format!(
-                "Expected SEMICOLON, got {:?}",
-                self.current_token().unwrap().token_type
-            )));
+            return Err(
+                self.unexpected(format!("Expected SEMICOLON, got {:?}", self.current_type()))
+            );
         }
-        Ok(Stmt::VAR(Box::new(name.unwrap()), initializer))
+        Ok(Stmt::new(
+            StmtKind::VAR(Box::new(name), type_, initializer),
+            self.span_since(start),
+        ))
+    }
+
+    //constDecl      → "const" IDENTIFIER "=" expression ";" ;
+    //The value can not be left out, it could never be given later
+    fn const_declaration(&mut self) -> StmtParserResult {
+        let start = self.current_span();
+        self.consume_advance_return(TokenType::CONST)?;
+        let name = self
+            .consume_advance_return(TokenType::IDENTIFIER("".to_string()))?
+            .clone();
+        let type_ = self.type_annotation()?.map(Box::new);
+        self.consume_advance_return(TokenType::EQUAL)?;
+        let value = self.expr_rule()?;
+        self.consume_advance_return(TokenType::SEMICOLON)?;
+        Ok(Stmt::new(
+            StmtKind::CONST(Box::new(name), type_, Box::new(value)),
+            self.span_since(start),
+        ))
     }
 
+    //ifStmt         → "if" "(" expression ")" statement ( "else" statement )? ;
     fn if_stmt(&mut self) -> StmtParserResult {
-        // "if"        "(" expr ")" block
+        // "if"        "(" expr ")" statement
         //   |          | Handle |  Handle
         //Start here   Consume both
 
-        //Jump the if
-        self.advance();
-        //Be sure that there is a (
-        self.consume_advance_return(TokenType::LEFTPAREN)?;
-        //Handle the boolean condition
-        let condition = self.expr_rule()?;
-        //Be sure that there is a )
-        self.consume_advance_return(TokenType::RIGHTPAREN)?;
-        //Be sure that there is a {
-        let curr = self.current_token().unwrap();
-        if curr.token_type != TokenType::LEFTBRACE {
-            return Err(ParserError::UnexpectedToken(format!(
-                "Expected {:?}, got {:?}",
-                curr.token_type, curr
-            )));
-        }
-        //The block will handle the closing }
-        let main_block = self.block_stmt()?;
+        //An else if chain is read in a loop, so a long chain does not count
+        //as nesting. Every if of the chain is kept as (start, condition, body)
+        let mut branches: Vec<(Span, Expr, Stmt)> = Vec::new();
         let mut else_block: Option<Box<Stmt>> = None;
-        if self.consume(TokenType::ELSE) {
-            else_block = Some(Box::new(self.block_stmt()?));
+        loop {
+            let start = self.current_span();
+            //Jump the if
+            self.advance();
+            //Be sure that there is a (
+            self.consume_advance_return(TokenType::LEFTPAREN)?;
+            //Handle the boolean condition
+            let condition = self.expr_rule()?;
+            //Be sure that there is a )
+            self.consume_advance_return(TokenType::RIGHTPAREN)?;
+            branches.push((start, condition, self.body()?));
+
+            //The else is taken by the closest if, so in if (a) if (b) x; else y;
+            //it belongs to if (b), the body parses it before this loop sees it
+            if !self.consume(TokenType::ELSE) {
+                break;
+            }
+            if *self.current_type() != TokenType::IF {
+                else_block = Some(Box::new(self.body()?));
+                break;
+            }
         }
 
-        Ok(Stmt::IF(
-            Box::new(condition),
-            Box::new(main_block),
-            else_block,
-        ))
+        //The chain is still an if whose else body is the next if.
+        //All of them end where the last else ends
+        let mut chain = else_block;
+        while let Some((start, condition, body)) = branches.pop() {
+            chain = Some(Box::new(Stmt::new(
+                StmtKind::IF(Box::new(condition), Box::new(body), chain),
+                self.span_since(start),
+            )));
+        }
+        match chain {
+            Some(stmt) => Ok(*stmt),
+            None => Err(self.unexpected(String::from("Expected an if statement"))),
+        }
     }
 }
 
@@ -458,256 +910,213 @@ impl<'a> Parser<'a> {
 impl<'a> Parser<'a> {
     // expression     → assignment ;
     pub fn expr_rule(&mut self) -> ExprParserResult {
-        if self.is_at_end() {
-            return Ok(Expr::Nil);
-        }
-        self.assignment_rule()
+        self.nested(Self::assignment_rule)
     }
 
-    //assignment     → IDENTIFIER "=" assignment
-    //               | logic_or ;
+    //assignment     → IDENTIFIER ( "=" | "+=" | "-=" | "*=" | "/=" ) assignment
+    //               | conditional ;
     pub fn assignment_rule(&mut self) -> ExprParserResult {
         //This can be a equality_expr or an identifier result
-        let e = self.logic_or()?;
-
-        let curr = self.current_token();
-
-        if curr.is_none() {
-            return Err(ParserError::UnexpectedToken(String::from("Unexpected EOF")));
-        }
-
-        match curr.unwrap().token_type {
+        let mut e = self.conditional_rule()?;
+
+        match self.current_type() {
+            TokenType::PLUSEQUAL
+            | TokenType::MINUSEQUAL
+            | TokenType::STAREQUAL
+            | TokenType::SLASHEQUAL => {
+                let operator = Box::new(self.advance_cloned()?);
+                let value = Box::new(self.nested(Self::assignment_rule)?);
+                let span = e.span.merge(&value.span);
+                //The target is evaluated once, a.b().c += 1 only calls b one time
+                match std::mem::replace(&mut e.kind, ExprKind::Nil) {
+                    ExprKind::Variable(name, depth) => Ok(Expr::new(
+                        ExprKind::CompoundAssignment(name, operator, value, depth),
+                        span,
+                    )),
+                    ExprKind::Get(object, name) => Ok(Expr::new(
+                        ExprKind::CompoundSet(object, name, operator, value),
+                        span,
+                    )),
+                    _ => Err(ParserError::NonValidAssigmentTarget(e.span)),
+                }
+            }
             TokenType::EQUAL => {
                 self.advance();
-                let assigment_value = self.assignment_rule()?;
-                match e {
-                    Expr::Variable(name) => {
-                        return Ok(Expr::Assignment(name, Box::new(assigment_value)))
-                    }
-                    _ => return Err(ParserError::NonValidAssigmentTarget),
+                let assigment_value = self.nested(Self::assignment_rule)?;
+                let span = e.span.merge(&assigment_value.span);
+                match std::mem::replace(&mut e.kind, ExprKind::Nil) {
+                    ExprKind::Variable(name, depth) => Ok(Expr::new(
+                        ExprKind::Assignment(name, Box::new(assigment_value), depth),
+                        span,
+                    )),
+                    //obj.field = value
+                    ExprKind::Get(object, name) => Ok(Expr::new(
+                        ExprKind::Set(object, name, Box::new(assigment_value)),
+                        span,
+                    )),
+                    _ => Err(ParserError::NonValidAssigmentTarget(e.span)),
                 }
             }
-            _ => return Ok(e),
+            _ => Ok(e),
         }
     }
-    // logic_or       → logic_and ("or" logic_and)* ;
-    pub fn logic_or(&mut self) -> ExprParserResult {
-        let left_expr = self.logic_and()?;
-        let curr = self.current_token();
-
-        if curr.is_none() {
-            return Err(ParserError::UnexpectedToken(String::from("Unexpected EOF")));
+    //conditional    → logic_or ( "?" expression ":" conditional )? ;
+    //The else branch is a conditional too, so a ? b : c ? d : e is grouped
+    //to the right: a ? b : (c ? d : e)
+    pub fn conditional_rule(&mut self) -> ExprParserResult {
+        let condition = self.binary_rule(Precedence::Or)?;
+        if !self.consume(TokenType::QUESTION) {
+            return Ok(condition);
         }
 
-        match curr.unwrap().token_type {
-            TokenType::OR => {
-                let operator = curr.unwrap().clone();
-                self.advance();
-                let right_expr = self.logic_and()?;
-
-                Ok(Expr::Logical(
-                    Box::new(left_expr),
-                    Box::new(operator),
-                    Box::new(right_expr),
-                ))
-            }
-            _ => Ok(left_expr),
-        }
+        let then = self.expr_rule()?;
+        self.consume_advance_return(TokenType::COLON)?;
+        let else_ = self.nested(Self::conditional_rule)?;
+        let span = condition.span.merge(&else_.span);
+        Ok(Expr::new(
+            ExprKind::Conditional(Box::new(condition), Box::new(then), Box::new(else_)),
+            span,
+        ))
     }
 
-    // logic_and      → equality ("and" equality)* ;
-    pub fn logic_and(&mut self) -> ExprParserResult {
-        let left = self.equality_rule()?;
-
-        let curr = self.current_token();
+    //Precedence climbing. Parses an operand and then every operator that
+    //binds at least as tight as the received precedence. The right operand
+    //only takes operators that bind tighter, so chains like 1 - 2 - 3 are
+    //grouped to the left: ((1 - 2) - 3)
+    pub fn binary_rule(&mut self, min_precedence: Precedence) -> ExprParserResult {
+        let mut left = self.unary_rule()?;
 
-        if curr.is_none() {
-            return Err(ParserError::UnexpectedToken(String::from("Unexpected EOF")));
-        }
-
-        match curr.unwrap().token_type {
-            TokenType::AND => {
-                let operator = curr.unwrap().clone();
-                self.advance();
-                let right = self.equality_rule()?;
-                return Ok(Expr::Logical(
-                    Box::new(left),
-                    Box::new(operator),
-                    Box::new(right),
-                ));
+        while let Some(operator) = self.current_token().and_then(binary_operator) {
+            if operator.precedence < min_precedence {
+                break;
             }
-            _ => return Ok(left),
-        }
-    }
-
-    //equality       → comparison ( ( "!=" | "==" ) comparison )* ;
-    //                              '-------------' -> Match
-    //                            '-----------------------------'-> Loop
-    pub fn equality_rule(&mut self) -> ExprParserResult {
-        let left = self.comparison_rule()?;
-        let curr_tkn = self.current_token();
-        if curr_tkn.is_none() {
-            return Err(ParserError::UnexpectedToken(String::from("Unexpected EOF")));
+            let token = self.advance_cloned()?;
+            let right = self.binary_rule(operator.precedence.next())?;
+            left = match operator.logical {
+                true => binary_node(ExprKind::Logical, left, token, right),
+                false => binary_node(ExprKind::Binary, left, token, right),
+            };
         }
 
-        match curr_tkn.unwrap().token_type {
-            TokenType::BANGEQUAL | TokenType::EQUALEQUAL => {
-                let operator = curr_tkn.unwrap().clone();
-                self.advance();
-                let right = self.comparison_rule()?;
-                return Ok(Expr::Binary(
-                    Box::new(left),
-                    Box::new(operator.clone()),
-                    Box::new(right),
-                ));
-            }
-            _ => {
-                return Ok(left);
-            }
-        }
+        Ok(left)
     }
 
-    //comparison     → term ( ( ">" | ">=" | "<" | "<=" ) term )* ;
-    //                        '-------------------------' -> Match
-    //                      '----------------------------------' -> While || Undf
-    pub fn comparison_rule(&mut self) -> ExprParserResult {
-        let left = self.term_rule()?;
-
-        let curr_tkn = self.current_token();
-        if curr_tkn.is_none() {
-            return Err(ParserError::UnexpectedToken(String::from("Unexpected EOF")));
-        }
+    //		unary          → ( "!" | "-" ) unary    -
+    //                       '-----------' -> Match  | -> Match entre ambos
+    // 		               | primary ;              -
+    pub fn unary_rule(&mut self) -> ExprParserResult {
+        match self.current_type() {
+            TokenType::BANG | TokenType::MINUS => {
+                let operator = self.advance_cloned()?;
+                let u = self.nested(Self::unary_rule)?;
 
-        match curr_tkn.unwrap().token_type {
-            TokenType::GREATER
-            | TokenType::GREATEREQUAL
-            | TokenType::LESS
-            | TokenType::LESSEQUAL => {
-                let operator = curr_tkn.unwrap().clone();
-                self.advance();
-                let right = self.term_rule()?;
-                return Ok(Expr::Binary(
-                    Box::new(left),
-                    Box::new(operator.clone()),
-                    Box::new(right),
-                ));
-            }
-            _ => {
-                return Ok(left);
+                let span = operator.span.merge(&u.span);
+                Ok(Expr::new(
+                    ExprKind::Unary(Box::new(operator), Box::new(u)),
+                    span,
+                ))
             }
+            _ => self.call_rule(),
         }
     }
 
-    //     term           → factor ( ( "-" | "+" ) factor )* ;
-    //                               '-----------' -> Match
-    //                             '----------------------' -> While || Undf
-    pub fn term_rule(&mut self) -> ExprParserResult {
-        let left = self.factor_rule()?;
-
-        let curr_tkn = self.current_token();
-        if curr_tkn.is_none() {
-            return Err(ParserError::UnexpectedToken(String::from("Unexpected EOF")));
-        }
-
-        match curr_tkn.unwrap().token_type {
-            TokenType::MINUS | TokenType::PLUS => {
-                let operator = curr_tkn.unwrap().clone();
-                self.advance();
-                let right = self.factor_rule()?;
-                return Ok(Expr::Binary(
-                    Box::new(left),
-                    Box::new(operator.clone()),
-                    Box::new(right),
-                ));
+    //call           → primary ( "(" arguments? ")" | "." IDENTIFIER )* ;
+    //Every ( after the callee is a new call over the previous result, ie. f(1)(2)
+    //and every . is a property access over it, ie. obj.method().field
+    pub fn call_rule(&mut self) -> ExprParserResult {
+        let mut expr = self.primary_rule()?;
+
+        while let Some(tkn) = self.current_token() {
+            match tkn.token_type {
+                TokenType::LEFTPAREN => {
+                    self.advance();
+                    expr = self.finish_call(expr)?;
+                }
+                TokenType::DOT => {
+                    self.advance();
+                    let name = self
+                        .consume_advance_return(TokenType::IDENTIFIER("".to_string()))?
+                        .clone();
+                    let span = expr.span.merge(&name.span);
+                    expr = Expr::new(ExprKind::Get(Box::new(expr), Box::new(name)), span);
+                }
+                _ => break,
             }
-            _ => return Ok(left),
         }
-    }
 
-    //		factor         → unary ( ( "/" | "*" ) unary )* ;
-    //                               '-----------' -> Match
-    //                             '---------------------' -> While || Undf
-    pub fn factor_rule(&mut self) -> ExprParserResult {
-        let left = self.unary_rule()?;
-
-        let curr_tkn = self.current_token();
-        if curr_tkn.is_none() {
-            return Err(ParserError::UnexpectedToken(String::from("Unexpected EOF")));
-        }
-
-        match curr_tkn.unwrap().token_type {
-            TokenType::SLASH | TokenType::STAR => {
-                let operator = curr_tkn.unwrap().clone();
-                self.advance();
-                let right = self.unary_rule()?;
-                return Ok(Expr::Binary(
-                    Box::new(left),
-                    Box::new(operator.clone()),
-                    Box::new(right),
-                ));
-            }
-            _ => return Ok(left),
-        }
+        Ok(expr)
     }
 
-    //		unary          → ( "!" | "-" ) unary    -
-    //                       '-----------' -> Match  | -> Match entre ambos
-    // 		               | primary ;              -
-    pub fn unary_rule(&mut self) -> ExprParserResult {
-        let curr_tkn = self.current_token();
-        if let None = curr_tkn {
-            return Err(ParserError::UnexpectedToken(String::from("Unexpected EOF")));
-        }
-        match curr_tkn.unwrap().token_type {
-            TokenType::BANG | TokenType::MINUS => {
-                let operator = curr_tkn.unwrap().clone();
-                self.advance();
-                let u = self.unary_rule()?;
-
-                return Ok(Expr::Unary(Box::new(operator), Box::new(u)));
-            }
-            _ => {
-                return self.primary_rule();
+    //arguments      → expression ( "," expression )* ;
+    fn finish_call(&mut self, callee: Expr) -> ExprParserResult {
+        let mut arguments: Vec<Box<Expr>> = Vec::new();
+        if self.current_token().map(|t| &t.token_type) != Some(&TokenType::RIGHTPAREN) {
+            loop {
+                if arguments.len() >= MAX_ARGUMENTS {
+                    return Err(ParserError::TooManyArguments(self.current_span()));
+                }
+                arguments.push(Box::new(self.expr_rule()?));
+                if !self.consume(TokenType::COMMA) {
+                    break;
+                }
             }
         }
+        let paren = self.consume_advance_return(TokenType::RIGHTPAREN)?.clone();
+
+        let span = callee.span.merge(&paren.span);
+        Ok(Expr::new(
+            ExprKind::Call(Box::new(callee), Box::new(paren), arguments),
+            span,
+        ))
     }
     //primary        → NUMBER | STRING | "true" | "false" | "nil"
     //                | "(" expression ")" ;
     pub fn primary_rule(&mut self) -> Result<Expr, ParserError> {
-        let curr_tkn = self.get_current_and_advance();
-        if let None = curr_tkn {
-            return Err(ParserError::UnexpectedToken(String::from("Unexpected EOF")));
-        }
-        let expr: Expr;
-        match &curr_tkn.unwrap().token_type {
-            TokenType::TRUE => expr = Expr::Boolean(true),
-            TokenType::FALSE => expr = Expr::Boolean(false),
-            TokenType::NUMBER(n) => return Ok(Expr::NumberLit(*n)),
+        let start = self.current_span();
+        //The EOF is not consumed, there must be nothing after it
+        if *self.current_type() == TokenType::EOF {
+            return Err(self.unexpected(String::from("Expected expression, got EOF")));
+        }
+        let curr_tkn = self.advance_cloned()?;
+        let expr = match &curr_tkn.token_type {
+            TokenType::TRUE => ExprKind::Boolean(true),
+            TokenType::FALSE => ExprKind::Boolean(false),
+            TokenType::NIL => ExprKind::Nil,
+            TokenType::THIS => ExprKind::This(Box::new(curr_tkn.clone()), Depth::default()),
+            //super is only valid as super.method
+            TokenType::SUPER => {
+                let keyword = curr_tkn.clone();
+                self.consume_advance_return(TokenType::DOT)?;
+                let method = self
+                    .consume_advance_return(TokenType::IDENTIFIER("".to_string()))?
+                    .clone();
+                ExprKind::Super(Box::new(keyword), Box::new(method), Depth::default())
+            }
+            TokenType::NUMBER(n) => ExprKind::NumberLit(*n),
             //This clone is not the best, because a new string is being created, but i dunno how
             //to handle the borrow checker correctly
-            TokenType::STRING(s) => expr = Expr::StringLit(Box::new(s.clone())),
-            TokenType::IDENTIFIER(s) => expr = Expr::Variable(Box::new(s.clone())),
+            TokenType::STRING(s) => ExprKind::StringLit(Box::new(s.clone())),
+            TokenType::IDENTIFIER(s) => ExprKind::Variable(Box::new(s.clone()), Depth::default()),
             TokenType::LEFTPAREN => {
                 //todo:Make it more rusty
                 let internal_expr: Expr = self.expr_rule()?;
                 if !self.consume(TokenType::RIGHTPAREN) {
-                    return Err(ParserError::UnexpectedToken(format!(
+                    return Err(self.unexpected(format!(
                         "Expected RIGHTPAREN (')'), got {:?}",
-                        self.current_token().unwrap()
+                        self.current_type()
                     )));
                 }
-                expr = Expr::Grouping(Box::new(internal_expr));
-                //Return ther expr
+                ExprKind::Grouping(Box::new(internal_expr))
             }
-            TokenType::EOF => expr = self.expr_rule()?,
-            _ => {
-                return Err(ParserError::UnexpectedToken(format!(
-                    "Got token: {:?}",
-                    self.current_token()
-                )));
+            //The token was already consumed, so the error points to it and not to the current one
+            tt => {
+                return Err(ParserError::UnexpectedToken(
+                    format!("Expected expression, got {:?}", tt),
+                    start,
+                ));
             }
-        }
-        Ok(expr)
+        };
+        Ok(Expr::new(expr, self.span_since(start)))
     }
 }
 
@@ -716,10 +1125,13 @@ mod parser_tests {
 
     use crate::{
         ast::{
-            parser::Parser,
+            expr::ExprKind,
+            parser::{Parser, ParserError},
             printer::AstPrinter,
             scanner::Scanner,
-            token::{self, Token},
+            span::Span,
+            stmt::{Stmt, StmtKind},
+            token::{self, Token, TokenType},
         },
         lox::Lox,
     };
@@ -740,10 +1152,18 @@ mod parser_tests {
 
     #[test]
     fn parse_literal() {
-        let tkn = Token::new(token::TokenType::NUMBER(32.0), 32.0.to_string(), 1);
+        let tkn = Token::new(
+            token::TokenType::NUMBER(32.0),
+            32.0.to_string(),
+            Span::default(),
+        );
 
-        let semicolon_tkn = Token::new(token::TokenType::SEMICOLON, ";".to_string(), 1);
-        let eof_tkn = Token::new(token::TokenType::EOF, "eof".to_string(), 1);
+        let semicolon_tkn = Token::new(
+            token::TokenType::SEMICOLON,
+            ";".to_string(),
+            Span::default(),
+        );
+        let eof_tkn = Token::new(token::TokenType::EOF, "eof".to_string(), Span::default());
 
         let expr = Parser::new(&vec![tkn, semicolon_tkn, eof_tkn])
             .parse()
@@ -757,14 +1177,22 @@ mod parser_tests {
         let idnt_tkn = Token::new(
             token::TokenType::IDENTIFIER("x".to_string()),
             "x".to_string(),
-            1,
+            Span::default(),
         );
 
-        let equal_tkn = Token::new(token::TokenType::EQUAL, "=".to_string(), 1);
-        let expr = Token::new(token::TokenType::NUMBER(3.0), "3.0".to_string(), 1);
+        let equal_tkn = Token::new(token::TokenType::EQUAL, "=".to_string(), Span::default());
+        let expr = Token::new(
+            token::TokenType::NUMBER(3.0),
+            "3.0".to_string(),
+            Span::default(),
+        );
 
-        let semicolon_tkn = Token::new(token::TokenType::SEMICOLON, ";".to_string(), 1);
-        let eof_tkn = Token::new(token::TokenType::EOF, "eof".to_string(), 1);
+        let semicolon_tkn = Token::new(
+            token::TokenType::SEMICOLON,
+            ";".to_string(),
+            Span::default(),
+        );
+        let eof_tkn = Token::new(token::TokenType::EOF, "eof".to_string(), Span::default());
 
         let expr = Parser::new(&vec![idnt_tkn, equal_tkn, expr, semicolon_tkn, eof_tkn])
             .assignment_rule()
@@ -776,10 +1204,14 @@ mod parser_tests {
     #[test]
     fn parse_string() {
         let str = String::from("Im a simple string");
-        let tkn = Token::new(token::TokenType::STRING(str.clone()), str, 1);
+        let tkn = Token::new(token::TokenType::STRING(str.clone()), str, Span::default());
 
-        let semicolon_tkn = Token::new(token::TokenType::SEMICOLON, ";".to_string(), 1);
-        let eof_tkn = Token::new(token::TokenType::EOF, "eof".to_string(), 1);
+        let semicolon_tkn = Token::new(
+            token::TokenType::SEMICOLON,
+            ";".to_string(),
+            Span::default(),
+        );
+        let eof_tkn = Token::new(token::TokenType::EOF, "eof".to_string(), Span::default());
 
         let expr = Parser::new(&vec![tkn, semicolon_tkn, eof_tkn])
             .parse()
@@ -790,10 +1222,18 @@ mod parser_tests {
 
     #[test]
     fn parse_boolean() {
-        let bool_tkn = Token::new(token::TokenType::FALSE, String::from("false"), 1);
+        let bool_tkn = Token::new(
+            token::TokenType::FALSE,
+            String::from("false"),
+            Span::default(),
+        );
 
-        let semicolon_tkn = Token::new(token::TokenType::SEMICOLON, ";".to_string(), 1);
-        let eof_tkn = Token::new(token::TokenType::EOF, "eof".to_string(), 1);
+        let semicolon_tkn = Token::new(
+            token::TokenType::SEMICOLON,
+            ";".to_string(),
+            Span::default(),
+        );
+        let eof_tkn = Token::new(token::TokenType::EOF, "eof".to_string(), Span::default());
 
         let expr = Parser::new(&vec![bool_tkn, semicolon_tkn, eof_tkn])
             .parse()
@@ -805,12 +1245,28 @@ mod parser_tests {
     #[test]
     fn parse_equality_literal() {
         //2 == 3 -> (== 2 3)
-        let two_tkn = Token::new(token::TokenType::NUMBER(2.0), 2.0.to_string(), 1);
-        let eqeq_tkn = Token::new(token::TokenType::EQUALEQUAL, "==".to_string(), 1);
-        let three_tkn = Token::new(token::TokenType::NUMBER(3.0), 3.0.to_string(), 1);
+        let two_tkn = Token::new(
+            token::TokenType::NUMBER(2.0),
+            2.0.to_string(),
+            Span::default(),
+        );
+        let eqeq_tkn = Token::new(
+            token::TokenType::EQUALEQUAL,
+            "==".to_string(),
+            Span::default(),
+        );
+        let three_tkn = Token::new(
+            token::TokenType::NUMBER(3.0),
+            3.0.to_string(),
+            Span::default(),
+        );
 
-        let semicolon_tkn = Token::new(token::TokenType::SEMICOLON, ";".to_string(), 1);
-        let eof_tkn = Token::new(token::TokenType::EOF, "eof".to_string(), 1);
+        let semicolon_tkn = Token::new(
+            token::TokenType::SEMICOLON,
+            ";".to_string(),
+            Span::default(),
+        );
+        let eof_tkn = Token::new(token::TokenType::EOF, "eof".to_string(), Span::default());
         let expr = Parser::new(&vec![two_tkn, eqeq_tkn, three_tkn, semicolon_tkn, eof_tkn]).parse();
         // .unwrap();
         println!("{:?}", expr);
@@ -822,12 +1278,24 @@ mod parser_tests {
     #[test]
     fn parse_comparison_literal() {
         //2 > 3 -> (> 2 3)
-        let two_tkn = Token::new(token::TokenType::NUMBER(2.0), 2.0.to_string(), 1);
-        let grtr_tkn = Token::new(token::TokenType::GREATER, ">".to_string(), 1);
-        let three_tkn = Token::new(token::TokenType::NUMBER(3.0), 3.0.to_string(), 1);
+        let two_tkn = Token::new(
+            token::TokenType::NUMBER(2.0),
+            2.0.to_string(),
+            Span::default(),
+        );
+        let grtr_tkn = Token::new(token::TokenType::GREATER, ">".to_string(), Span::default());
+        let three_tkn = Token::new(
+            token::TokenType::NUMBER(3.0),
+            3.0.to_string(),
+            Span::default(),
+        );
 
-        let semicolon_tkn = Token::new(token::TokenType::SEMICOLON, ";".to_string(), 1);
-        let eof_tkn = Token::new(token::TokenType::EOF, "eof".to_string(), 1);
+        let semicolon_tkn = Token::new(
+            token::TokenType::SEMICOLON,
+            ";".to_string(),
+            Span::default(),
+        );
+        let eof_tkn = Token::new(token::TokenType::EOF, "eof".to_string(), Span::default());
 
         let expr = Parser::new(&vec![two_tkn, grtr_tkn, three_tkn, semicolon_tkn, eof_tkn])
             .parse()
@@ -840,12 +1308,24 @@ mod parser_tests {
     #[test]
     fn parse_term_literal() {
         //2 + 3 -> (+ 2 3)
-        let two_tkn = Token::new(token::TokenType::NUMBER(2.0), 2.0.to_string(), 1);
-        let plus_tkn = Token::new(token::TokenType::PLUS, "+".to_string(), 1);
-        let three_tkn = Token::new(token::TokenType::NUMBER(3.0), 3.0.to_string(), 1);
+        let two_tkn = Token::new(
+            token::TokenType::NUMBER(2.0),
+            2.0.to_string(),
+            Span::default(),
+        );
+        let plus_tkn = Token::new(token::TokenType::PLUS, "+".to_string(), Span::default());
+        let three_tkn = Token::new(
+            token::TokenType::NUMBER(3.0),
+            3.0.to_string(),
+            Span::default(),
+        );
 
-        let semicolon_tkn = Token::new(token::TokenType::SEMICOLON, ";".to_string(), 1);
-        let eof_tkn = Token::new(token::TokenType::EOF, "eof".to_string(), 1);
+        let semicolon_tkn = Token::new(
+            token::TokenType::SEMICOLON,
+            ";".to_string(),
+            Span::default(),
+        );
+        let eof_tkn = Token::new(token::TokenType::EOF, "eof".to_string(), Span::default());
         let expr = Parser::new(&vec![two_tkn, plus_tkn, three_tkn, semicolon_tkn, eof_tkn])
             .parse()
             .unwrap();
@@ -857,12 +1337,24 @@ mod parser_tests {
     #[test]
     fn parse_factor_literal() {
         //3 * 4 -> (* 3 4)
-        let three_tkn = Token::new(token::TokenType::NUMBER(3.0), 3.0.to_string(), 1);
-        let star_tkn = Token::new(token::TokenType::STAR, "*".to_string(), 1);
-        let four_tkn = Token::new(token::TokenType::NUMBER(4.0), 4.0.to_string(), 1);
+        let three_tkn = Token::new(
+            token::TokenType::NUMBER(3.0),
+            3.0.to_string(),
+            Span::default(),
+        );
+        let star_tkn = Token::new(token::TokenType::STAR, "*".to_string(), Span::default());
+        let four_tkn = Token::new(
+            token::TokenType::NUMBER(4.0),
+            4.0.to_string(),
+            Span::default(),
+        );
 
-        let semicolon_tkn = Token::new(token::TokenType::SEMICOLON, ";".to_string(), 1);
-        let eof_tkn = Token::new(token::TokenType::EOF, "eof".to_string(), 1);
+        let semicolon_tkn = Token::new(
+            token::TokenType::SEMICOLON,
+            ";".to_string(),
+            Span::default(),
+        );
+        let eof_tkn = Token::new(token::TokenType::EOF, "eof".to_string(), Span::default());
         let expr = Parser::new(&vec![three_tkn, star_tkn, four_tkn, semicolon_tkn, eof_tkn])
             .parse()
             .unwrap();
@@ -873,11 +1365,19 @@ mod parser_tests {
 
     #[test]
     fn parse_unary() {
-        let bang_tkn = Token::new(token::TokenType::BANG, "!".to_string(), 1);
-        let number_tkn = Token::new(token::TokenType::NUMBER(32.0), 32.0.to_string(), 1);
+        let bang_tkn = Token::new(token::TokenType::BANG, "!".to_string(), Span::default());
+        let number_tkn = Token::new(
+            token::TokenType::NUMBER(32.0),
+            32.0.to_string(),
+            Span::default(),
+        );
 
-        let semicolon_tkn = Token::new(token::TokenType::SEMICOLON, ";".to_string(), 1);
-        let eof_tkn = Token::new(token::TokenType::EOF, "eof".to_string(), 1);
+        let semicolon_tkn = Token::new(
+            token::TokenType::SEMICOLON,
+            ";".to_string(),
+            Span::default(),
+        );
+        let eof_tkn = Token::new(token::TokenType::EOF, "eof".to_string(), Span::default());
         let expr = Parser::new(&vec![bang_tkn, number_tkn, semicolon_tkn, eof_tkn])
             .parse()
             .unwrap();
@@ -889,12 +1389,28 @@ mod parser_tests {
     #[test]
     fn parse_grouped_number_literal() {
         // Parse -> (32.0)
-        let left_paren = Token::new(token::TokenType::LEFTPAREN, "(".to_string(), 1);
-        let right_paren = Token::new(token::TokenType::RIGHTPAREN, ")".to_string(), 1);
-        let number_tkn = Token::new(token::TokenType::NUMBER(32.0), 32.0.to_string(), 1);
+        let left_paren = Token::new(
+            token::TokenType::LEFTPAREN,
+            "(".to_string(),
+            Span::default(),
+        );
+        let right_paren = Token::new(
+            token::TokenType::RIGHTPAREN,
+            ")".to_string(),
+            Span::default(),
+        );
+        let number_tkn = Token::new(
+            token::TokenType::NUMBER(32.0),
+            32.0.to_string(),
+            Span::default(),
+        );
 
-        let semicolon_tkn = Token::new(token::TokenType::SEMICOLON, ";".to_string(), 1);
-        let eof_tkn = Token::new(token::TokenType::EOF, "eof".to_string(), 1);
+        let semicolon_tkn = Token::new(
+            token::TokenType::SEMICOLON,
+            ";".to_string(),
+            Span::default(),
+        );
+        let eof_tkn = Token::new(token::TokenType::EOF, "eof".to_string(), Span::default());
         let expr = Parser::new(&vec![
             left_paren,
             number_tkn,
@@ -910,18 +1426,265 @@ mod parser_tests {
         assert_eq!(printed, "(group 32)");
     }
 
+    fn tokens(src: &str) -> Vec<Token> {
+        let mut lox = Lox::default();
+        let mut scanner = Scanner::new(&mut lox, src);
+        scanner.scan_tokens().clone()
+    }
+
+    fn parse_source(src: &str) -> Result<Vec<Stmt>, Vec<ParserError>> {
+        Parser::new(&tokens(src)).parse()
+    }
+
+    fn print_source(src: &str) -> String {
+        AstPrinter.print_program(&parse_source(src).unwrap())
+    }
+
+    #[test]
+    fn binary_chains_are_left_associative() {
+        assert_eq!(print_source("1 + 2 + 3;"), "(+ (+ 1 2) 3)");
+        assert_eq!(print_source("1 - 2 - 3 - 4;"), "(- (- (- 1 2) 3) 4)");
+        assert_eq!(print_source("8 / 4 * 2;"), "(* (/ 8 4) 2)");
+        assert_eq!(
+            print_source("1 < 2 == 3 > 4 != true;"),
+            "(!= (== (< 1 2) (> 3 4)) true)"
+        );
+    }
+
+    #[test]
+    fn compound_assignments() {
+        assert_eq!(print_source("a += 1 + 2;"), "(+= a (+ 1 2))");
+        assert_eq!(print_source("a -= b *= 2;"), "(-= a (*= b 2))");
+        assert_eq!(print_source("a.b.c /= 2;"), "(/= (. a b) c 2)");
+        assert_eq!(print_source("a = b += 1;"), "(= a (+= b 1))");
+
+        assert!(matches!(
+            parse_source("a + b += 1;").unwrap_err()[0],
+            ParserError::NonValidAssigmentTarget(_)
+        ));
+    }
+
+    #[test]
+    fn conditionals_are_right_associative() {
+        assert_eq!(print_source("a ? b : c ? d : e;"), "(?: a b (?: c d e))");
+        assert_eq!(print_source("a ? b ? c : d : e;"), "(?: a (?: b c d) e)");
+        assert_eq!(print_source("a or b ? c : d;"), "(?: (or a b) c d)");
+        assert_eq!(print_source("a = b ? c : d;"), "(= a (?: b c d))");
+        assert_eq!(print_source("a ? b = 1 : c;"), "(?: a (= b 1) c)");
+    }
+
+    #[test]
+    fn bodies_can_be_any_statement() {
+        assert_eq!(print_source("if (a) print 1;"), "(if a (print 1))");
+        assert_eq!(
+            print_source("if (a) print 1; else b = 2;"),
+            "(if-else a (print 1) (= b 2))"
+        );
+        assert_eq!(
+            print_source("while (a) a = a - 1;"),
+            "(while a (= a (- a 1)))"
+        );
+        assert_eq!(
+            print_source("for (;;) print 1;"),
+            "(block (while true (block (print 1))))"
+        );
+    }
+
+    #[test]
+    fn else_if_chains() {
+        assert_eq!(
+            print_source("if (a) { print 1; } else if (b) { print 2; } else { print 3; }"),
+            "(if-else a (block (print 1)) (if-else b (block (print 2)) (block (print 3))))"
+        );
+    }
+
+    #[test]
+    fn long_else_if_chains_are_not_nesting() {
+        let branches: String = (1..300)
+            .map(|i| format!(" else if (a == {}) print {};", i, i))
+            .collect();
+        let printed = print_source(&format!("if (a == 0) print 0;{} else print a;", branches));
+        assert_eq!(printed.matches("(if-else").count(), 300);
+        assert!(printed.ends_with(&format!("(print 299) (print a){}", ")".repeat(300))));
+    }
+
+    #[test]
+    fn else_binds_to_the_closest_if() {
+        assert_eq!(
+            print_source("if (a) if (b) print 1; else print 2;"),
+            "(if a (if-else b (print 1) (print 2)))"
+        );
+        assert_eq!(
+            print_source("if (a) { if (b) print 1; } else print 2;"),
+            "(if-else a (block (if b (print 1))) (print 2))"
+        );
+    }
+
+    #[test]
+    fn declarations_are_not_bodies() {
+        for src in [
+            "if (a) var b = 1;",
+            "while (a) fun f() {}",
+            "if (a) {} else class A {}",
+        ] {
+            assert!(parse_source(src).is_err(), "{}", src);
+        }
+    }
+
+    #[test]
+    fn logical_chains() {
+        //a or b and c or d -> ((a or (b and c)) or d)
+        let src = String::from("a or b and c or d;");
+        let mut lox = Lox::default();
+        let mut scanner = Scanner::new(&mut lox, &src);
+        let tokens = scanner.scan_tokens();
+        let stmts = Parser::new(tokens).parse().unwrap();
+        let StmtKind::EXPR(e) = &stmts[0].kind else {
+            panic!("Expected an expression statement")
+        };
+        let ExprKind::Logical(left, operator, right) = &e.kind else {
+            panic!("Expected a logical expression, got {:?}", e)
+        };
+        assert_eq!(operator.token_type, TokenType::OR);
+        assert_eq!(AstPrinter::default().print_expr(right), "d");
+        let ExprKind::Logical(a, operator, and) = &left.kind else {
+            panic!("Expected a logical expression, got {:?}", left)
+        };
+        assert_eq!(operator.token_type, TokenType::OR);
+        assert_eq!(AstPrinter::default().print_expr(a), "a");
+        assert!(
+            matches!(&and.kind, ExprKind::Logical(_, op, _) if op.token_type == TokenType::AND)
+        );
+    }
+
+    #[test]
+    fn binary_precedence() {
+        assert_eq!(print_source("1 + 2 * 3 - 4;"), "(- (+ 1 (* 2 3)) 4)");
+        assert_eq!(print_source("-1 * 2 < 3 + 4;"), "(< (* (- 1) 2) (+ 3 4))");
+        assert_eq!(print_source("(1 + 2) * 3;"), "(* (group (+ 1 2)) 3)");
+    }
+
+    #[test]
+    fn parse_call() {
+        assert_eq!(print_source("add(1, 2)(3);"), "(call (call add 1 2) 3)");
+    }
+
+    #[test]
+    fn parse_fun_declaration() {
+        let stmts = parse_source("fun add(a, b) { return a + b; }").unwrap();
+        match &stmts[0].kind {
+            StmtKind::FUNCTION(decl) => {
+                assert_eq!(decl.name.lexeme, "add");
+                assert_eq!(decl.params.len(), 2);
+                assert_eq!(decl.body.len(), 1);
+            }
+            s => panic!("Expected a function declaration, got {:?}", s),
+        }
+    }
+
+    #[test]
+    fn parse_class_declaration() {
+        let src = "class A { init(a) { this.a = a; } get() { return this.a; } }";
+        let stmts = parse_source(src).unwrap();
+        match &stmts[0].kind {
+            StmtKind::CLASS(name, superclass, methods) => {
+                assert_eq!(name.lexeme, "A");
+                assert!(superclass.is_none());
+                assert_eq!(methods.len(), 2);
+                assert_eq!(methods[0].name.lexeme, "init");
+            }
+            s => panic!("Expected a class declaration, got {:?}", s),
+        }
+    }
+
+    #[test]
+    fn parse_subclass() {
+        let stmts = parse_source("class B < A { get() { return super.get(); } }").unwrap();
+        match &stmts[0].kind {
+            StmtKind::CLASS(_, Some(superclass), _) => {
+                assert_eq!(AstPrinter::default().print_expr(superclass), "A");
+            }
+            s => panic!("Expected a subclass declaration, got {:?}", s),
+        }
+    }
+
+    #[test]
+    fn nodes_carry_spans() {
+        let stmts = parse_source("var a = 1;\nprint a + 22;").unwrap();
+        assert_eq!(stmts[0].span, Span::new(0, 0, 10, 1, 1));
+        assert_eq!(stmts[1].span, Span::new(0, 11, 24, 2, 1));
+        match &stmts[1].kind {
+            StmtKind::PRINT(e) => assert_eq!(e.span, Span::new(0, 17, 23, 2, 7)),
+            s => panic!("Expected a print, got {:?}", s),
+        }
+    }
+
+    #[test]
+    fn error_points_at_token() {
+        let errors = parse_source("print 1;\nprint 2 3;").unwrap_err();
+        assert_eq!(errors[0].span(), Span::new(0, 17, 18, 2, 9));
+    }
+
+    #[test]
+    fn reports_every_error() {
+        let tokens = tokens("var a = ;\nprint 1;\nvar = 2;\nprint 2 3;\nprint 4;");
+        let (stmts, errors) = Parser::new(&tokens).parse_partial();
+        let lines: Vec<usize> = errors.iter().map(|e| e.span().line).collect();
+        assert_eq!(lines, vec![1, 3, 4]);
+        //The valid statements are still there
+        assert_eq!(
+            AstPrinter::default().print_program(&stmts),
+            "(print 1)\n(print 4)"
+        );
+    }
+
+    #[test]
+    fn recovers_inside_blocks() {
+        let tokens = tokens("fun f() { print ; return 1; } class A { m() { 1 +; } } print 2;");
+        let (stmts, errors) = Parser::new(&tokens).parse_partial();
+        assert_eq!(errors.len(), 2);
+        assert_eq!(stmts.len(), 3);
+        match &stmts[0].kind {
+            StmtKind::FUNCTION(decl) => assert_eq!(decl.body.len(), 1),
+            s => panic!("Expected a function declaration, got {:?}", s),
+        }
+    }
+
+    #[test]
+    fn unclosed_input_is_an_error() {
+        for src in ["{ print 1;", "print (1", "1 +", "fun f() { return"] {
+            let errors = parse_source(src).unwrap_err();
+            assert!(errors[0].message().contains("EOF"), "{}", errors[0]);
+        }
+    }
+
+    #[test]
+    fn runs_out_of_tokens_without_eof() {
+        let minus = Token::new(TokenType::MINUS, "-".to_string(), Span::default());
+        let tokens = vec![minus.clone(), minus];
+        assert!(Parser::new(&tokens).parse().is_err());
+    }
+
     #[test]
     fn test_end_eof() {
-        let eof_tkn = Token::new(token::TokenType::EOF, "eof".to_string(), 1);
+        let eof_tkn = Token::new(token::TokenType::EOF, "eof".to_string(), Span::default());
         let is = Parser::new(&vec![eof_tkn]).is_at_end();
         assert!(is)
     }
     #[test]
     fn test_end_non_eof() {
-        let number_tkn = Token::new(token::TokenType::NUMBER(32.0), 32.0.to_string(), 1);
+        let number_tkn = Token::new(
+            token::TokenType::NUMBER(32.0),
+            32.0.to_string(),
+            Span::default(),
+        );
 
-        let semicolon_tkn = Token::new(token::TokenType::SEMICOLON, ";".to_string(), 1);
-        let eof_tkn = Token::new(token::TokenType::EOF, "eof".to_string(), 1);
+        let semicolon_tkn = Token::new(
+            token::TokenType::SEMICOLON,
+            ";".to_string(),
+            Span::default(),
+        );
+        let eof_tkn = Token::new(token::TokenType::EOF, "eof".to_string(), Span::default());
         let is_eof = Parser::new(&vec![number_tkn, semicolon_tkn, eof_tkn]).is_at_end();
         assert!(!is_eof)
     }
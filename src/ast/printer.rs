@@ -1,25 +1,23 @@
 use super::{
-    expr::{self, Expr},
-    stmt::{self, Stmt},
+    expr::{self, Expr, ExprKind},
+    stmt::{self, typed_name, FunctionDecl, Stmt, StmtKind},
     ExprVisitor, StmtVisitor,
 };
 
 /**
- * Will print the parsed AST
+ * Will print the parsed AST as S-expressions, ie. print 1 + 2; is (print (+ 1 2))
+ * Expression statements are printed as the bare expression
  */
 #[derive(Default)]
 pub struct AstPrinter;
 
-#[allow(dead_code)]
 impl AstPrinter {
+    //One line for every top level statement
     pub fn print_program(&self, e: &Vec<Stmt>) -> String {
-        let mut str = String::from("");
-
-        for s in e.iter() {
-            str += &self.print_stmt(s);
-        }
-
-        str
+        e.iter()
+            .map(|s| self.print_stmt(s))
+            .collect::<Vec<String>>()
+            .join("\n")
     }
 
     pub fn print_stmt(&self, e: &stmt::Stmt) -> String {
@@ -30,50 +28,145 @@ impl AstPrinter {
         self.visit_expr(e)
     }
     fn parenthesize(&self, name: &str, exprs: Vec<&Box<Expr>>) -> String {
+        let parts = exprs.iter().map(|e| self.visit_expr(e)).collect();
+        self.parenthesize_parts(name, parts)
+    }
+
+    //Same as parenthesize, for the parts that are not expressions
+    fn parenthesize_parts(&self, name: &str, parts: Vec<String>) -> String {
         let mut s = String::from("(");
         s += name;
 
-        for e in exprs.iter() {
+        for p in parts.iter() {
             s += " ";
-            s += &self.visit_expr(e);
+            s += p;
         }
         s += ")";
         s
     }
+
+    fn print_stmts(&self, stmts: &[Box<Stmt>]) -> Vec<String> {
+        stmts.iter().map(|s| self.visit_stmt(s)).collect()
+    }
+
+    //(fun name(a b) body...)
+    fn print_function(&self, decl: &FunctionDecl) -> String {
+        let mut name = format!(
+            "fun {}({})",
+            decl.name.lexeme,
+            decl.typed_params().join(" ")
+        );
+        if let Some(return_type) = &decl.return_type {
+            name += &format!(": {}", return_type.lexeme);
+        }
+        self.parenthesize_parts(&name, self.print_stmts(&decl.body))
+    }
 }
 
 impl StmtVisitor<String> for AstPrinter {
     fn visit_stmt(&self, b: &super::stmt::Stmt) -> String {
-        match b {
-            //Todo: Add some kind of parenthesize for stmt
-            Stmt::EXPR(e) => self.visit_expr(&e),
-            Stmt::PRINT(e) => self.visit_expr(&e),
-            Stmt::VAR(_, _) => todo!(),
-            Stmt::BLOCK(_) => todo!(),
-            Stmt::IF(_, _, _) => todo!(),
-            Stmt::WHILE(_, _) => todo!(),
+        match &b.kind {
+            StmtKind::EXPR(e) => self.visit_expr(&e),
+            StmtKind::PRINT(e) => self.parenthesize("print", vec![e]),
+            StmtKind::VAR(name, type_, initializer) => {
+                let name = typed_name(name, type_.as_deref());
+                match initializer {
+                    Some(value) => self.parenthesize(&format!("var {} =", name), vec![value]),
+                    None => self.parenthesize(&format!("var {}", name), vec![]),
+                }
+            }
+            StmtKind::CONST(name, type_, value) => {
+                let name = typed_name(name, type_.as_deref());
+                self.parenthesize(&format!("const {} =", name), vec![value])
+            }
+            StmtKind::BLOCK(stmts) => self.parenthesize_parts("block", self.print_stmts(stmts)),
+            StmtKind::IF(condition, then, else_) => {
+                let mut parts = vec![self.visit_expr(condition), self.visit_stmt(then)];
+                match else_ {
+                    Some(else_) => {
+                        parts.push(self.visit_stmt(else_));
+                        self.parenthesize_parts("if-else", parts)
+                    }
+                    None => self.parenthesize_parts("if", parts),
+                }
+            }
+            StmtKind::WHILE(condition, body) => self.parenthesize_parts(
+                "while",
+                vec![self.visit_expr(condition), self.visit_stmt(body)],
+            ),
+            StmtKind::FUNCTION(decl) => self.print_function(decl),
+            StmtKind::RETURN(_, value) => match value {
+                Some(value) => self.parenthesize("return", vec![value]),
+                None => self.parenthesize("return", vec![]),
+            },
+            StmtKind::CLASS(name, superclass, methods) => {
+                let mut header = format!("class {}", name.lexeme);
+                if let Some(superclass) = superclass {
+                    header += &format!(" < {}", self.visit_expr(superclass));
+                }
+                let methods = methods.iter().map(|m| self.print_function(m)).collect();
+                self.parenthesize_parts(&header, methods)
+            }
         }
     }
 }
 
 impl ExprVisitor<String> for AstPrinter {
     fn visit_expr(&self, b: &expr::Expr) -> String {
-        match b {
-            Expr::Binary(right, operator, left) => {
-                return self.parenthesize(&operator.lexeme, vec![right, left])
+        match &b.kind {
+            ExprKind::Binary(left, operator, right) | ExprKind::Logical(left, operator, right) => {
+                self.parenthesize(&operator.lexeme, vec![left, right])
+            }
+            ExprKind::Grouping(expression) => self.parenthesize("group", vec![expression]),
+            ExprKind::Conditional(condition, then, else_) => {
+                self.parenthesize("?:", vec![condition, then, else_])
+            }
+            ExprKind::Unary(operator, right) => self.parenthesize(&operator.lexeme, vec![right]),
+            ExprKind::NumberLit(value) => value.to_string(),
+            ExprKind::StringLit(value) => value.to_string(),
+            ExprKind::Boolean(v) => v.to_string(),
+            ExprKind::Assignment(name, value, _) => {
+                let target = Box::new(Expr::new(
+                    ExprKind::Variable(name.clone(), expr::Depth::default()),
+                    b.span,
+                ));
+                self.parenthesize("=", vec![&target, value])
             }
-            Expr::Grouping(expression) => return self.parenthesize("group", vec![expression]),
-            Expr::Unary(operator, right) => {
-                return self.parenthesize(&operator.lexeme, vec![right])
+            ExprKind::CompoundAssignment(name, operator, value, _) => self.parenthesize_parts(
+                &operator.lexeme,
+                vec![name.to_string(), self.visit_expr(value)],
+            ),
+            ExprKind::Variable(v, _) => v.to_string(),
+            ExprKind::Call(callee, _, arguments) => {
+                let mut exprs = vec![callee];
+                exprs.extend(arguments.iter());
+                self.parenthesize("call", exprs)
             }
-            Expr::NumberLit(value) => return value.to_string(),
-            Expr::StringLit(value) => return value.to_string(),
-            Expr::Boolean(v) => return v.to_string(),
-            Expr::Assignment(name, value) => {
-                return self.parenthesize("=", vec![&Box::new(Expr::Variable(name.clone())), value])
+            //(. object name) and (= object name value)
+            ExprKind::Get(object, name) => {
+                self.parenthesize_parts(".", vec![self.visit_expr(object), name.lexeme.clone()])
             }
-            Expr::Variable(v) => return v.to_string(),
-            _ => return String::from("nil"),
+            ExprKind::Set(object, name, value) => self.parenthesize_parts(
+                "=",
+                vec![
+                    self.visit_expr(object),
+                    name.lexeme.clone(),
+                    self.visit_expr(value),
+                ],
+            ),
+            ExprKind::CompoundSet(object, name, operator, value) => self.parenthesize_parts(
+                &operator.lexeme,
+                vec![
+                    self.visit_expr(object),
+                    name.lexeme.clone(),
+                    self.visit_expr(value),
+                ],
+            ),
+            ExprKind::This(_, _) => String::from("this"),
+            ExprKind::Super(_, method, _) => {
+                self.parenthesize_parts("super", vec![method.lexeme.clone()])
+            }
+            ExprKind::Nil => String::from("nil"),
         }
     }
 }
@@ -81,42 +174,72 @@ impl ExprVisitor<String> for AstPrinter {
 #[cfg(test)]
 mod printer_tests {
 
-    use crate::ast::token::{Token, TokenType};
+    use crate::{
+        ast::{
+            parser::Parser,
+            scanner::Scanner,
+            span::Span,
+            token::{Token, TokenType},
+        },
+        lox::Lox,
+    };
 
     use super::*;
 
+    fn print_source(src: &str) -> String {
+        let src = String::from(src);
+        let mut lox = Lox::default();
+        let mut scanner = Scanner::new(&mut lox, &src);
+        let tokens = scanner.scan_tokens();
+        let stmts = Parser::new(tokens).parse().unwrap();
+        AstPrinter::default().print_program(&stmts)
+    }
+
+    //The printer ignores the spans
+    fn node(kind: ExprKind) -> Box<Expr> {
+        Box::new(Expr::new(kind, Span::default()))
+    }
+
+    fn token(tt: TokenType, lexeme: &str) -> Box<Token> {
+        Box::new(Token::new(tt, lexeme.to_string(), Span::default()))
+    }
+
     #[test]
     fn print_number_literal() {
-        let number_literal = Expr::NumberLit(65.0);
+        let number_literal = node(ExprKind::NumberLit(65.0));
 
-        let result = AstPrinter::default().print_expr(&Box::new(number_literal));
+        let result = AstPrinter::default().print_expr(&number_literal);
         assert!(result == String::from("65"))
     }
 
     #[test]
     fn print_string_literal() {
-        let number_literal = Expr::StringLit(Box::new(String::from("Im string literal")));
+        let number_literal = node(ExprKind::StringLit(Box::new(String::from(
+            "Im string literal",
+        ))));
 
-        let result = AstPrinter::default().print_expr(&Box::new(number_literal));
+        let result = AstPrinter::default().print_expr(&number_literal);
         assert_eq!(result, String::from("Im string literal"))
     }
     #[test]
     fn print_factor() {
-        let star_tkn = Token::new(TokenType::STAR, "*".to_string(), 1);
-        let four_tkn = Expr::NumberLit(4.0);
-        let three_tkn = Expr::NumberLit(3.0);
-        let factor_expr = Expr::Binary(Box::new(three_tkn), Box::new(star_tkn), Box::new(four_tkn));
+        let star_tkn = token(TokenType::STAR, "*");
+        let four_tkn = node(ExprKind::NumberLit(4.0));
+        let three_tkn = node(ExprKind::NumberLit(3.0));
+        let factor_expr = node(ExprKind::Binary(three_tkn, star_tkn, four_tkn));
         let result = AstPrinter::default().print_expr(&factor_expr);
         assert_eq!(result, "(* 3 4)");
     }
 
     #[test]
     fn print_binary() {
-        let string_literal = Box::new(Expr::StringLit(Box::new(String::from("Im string literal"))));
-        let number_literal = Box::new(Expr::NumberLit(65.0));
-        let tkn: Box<Token> = Box::new(Token::new(TokenType::BANGEQUAL, "!=".to_string(), 0));
+        let string_literal = node(ExprKind::StringLit(Box::new(String::from(
+            "Im string literal",
+        ))));
+        let number_literal = node(ExprKind::NumberLit(65.0));
+        let tkn = token(TokenType::BANGEQUAL, "!=");
 
-        let binary_expr = Expr::Binary(number_literal, tkn, string_literal);
+        let binary_expr = node(ExprKind::Binary(number_literal, tkn, string_literal));
         let result = AstPrinter::default().print_expr(&binary_expr);
 
         assert_eq!(result, "(!= 65 Im string literal)".to_string())
@@ -124,16 +247,58 @@ mod printer_tests {
 
     #[test]
     fn print_complex() {
-        let minus_tkn = Box::new(Token::new(TokenType::MINUS, String::from("-"), 1));
-        let number_literal = Box::new(Expr::NumberLit(123.0));
-        let number_literal_2 = Box::new(Expr::NumberLit(45.67));
+        let minus_tkn = token(TokenType::MINUS, "-");
+        let number_literal = node(ExprKind::NumberLit(123.0));
+        let number_literal_2 = node(ExprKind::NumberLit(45.67));
 
-        let unary_expr = Box::new(Expr::Unary(minus_tkn, number_literal));
-        let start_tkn = Box::new(Token::new(TokenType::STAR, String::from("*"), 1));
-        let grouping_expr = Box::new(Expr::Grouping(number_literal_2));
+        let unary_expr = node(ExprKind::Unary(minus_tkn, number_literal));
+        let start_tkn = token(TokenType::STAR, "*");
+        let grouping_expr = node(ExprKind::Grouping(number_literal_2));
 
-        let binary = Box::new(Expr::Binary(unary_expr, start_tkn, grouping_expr));
+        let binary = node(ExprKind::Binary(unary_expr, start_tkn, grouping_expr));
         let result = AstPrinter::default().print_expr(&binary);
         assert_eq!(result, "(* (- 123) (group 45.67))".to_string());
     }
+
+    #[test]
+    fn print_statements() {
+        assert_eq!(print_source("print 1;"), "(print 1)");
+        assert_eq!(print_source("var a; var b = 2;"), "(var a)\n(var b = 2)");
+        assert_eq!(print_source("const c = 3;"), "(const c = 3)");
+        assert_eq!(
+            print_source("var a: number = 1; const c: nil = nil;"),
+            "(var a: number = 1)\n(const c: nil = nil)"
+        );
+        assert_eq!(
+            print_source("fun f(a: number, b): string { return b; }"),
+            "(fun f(a: number b): string (return b))"
+        );
+        assert_eq!(print_source("{ a = 1; {} }"), "(block (= a 1) (block))");
+        assert_eq!(
+            print_source("if (a) { print 1; } else { print 2; }"),
+            "(if-else a (block (print 1)) (block (print 2)))"
+        );
+        assert_eq!(
+            print_source("while (a < 3) { a = a + 1; }"),
+            "(while (< a 3) (block (= a (+ a 1))))"
+        );
+    }
+
+    #[test]
+    fn print_functions_and_classes() {
+        assert_eq!(
+            print_source("fun add(a, b) { return a + b; } fun f() { return; }"),
+            "(fun add(a b) (return (+ a b)))\n(fun f() (return))"
+        );
+        assert_eq!(
+            print_source("class B < A { init() { this.x = nil; } get() { return super.get(); } }"),
+            "(class B < A (fun init() (= this x nil)) (fun get() (return (call (super get)))))"
+        );
+        assert_eq!(print_source("a.b.c;"), "(. (. a b) c)");
+    }
+
+    #[test]
+    fn print_logical() {
+        assert_eq!(print_source("a and b or !c;"), "(or (and a b) (! c))");
+    }
 }
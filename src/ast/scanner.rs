@@ -3,7 +3,10 @@ use crate::lox::Lox;
 use std::iter::Peekable;
 use std::str::Chars;
 
-use super::token::{Token, TokenType};
+use super::{
+    span::Span,
+    token::{Comment, Token, TokenType},
+};
 
 //The source (The actual code) string is owned by
 //the scanner because after the execution of the
@@ -12,21 +15,37 @@ use super::token::{Token, TokenType};
 //Borrowing and lifetimes are the go to option
 pub struct Scanner<'a> {
     source: Peekable<Chars<'a>>,
-    start: u8,
-    line: u8,
-    current: u8,
+    file: usize,
+    start: usize, //Byte offset where the current token starts
+    start_line: usize,
+    start_column: usize,
+    current: usize, //Byte offset of the next char
+    line: usize,
+    column: usize,
     tokens: Vec<Token>,
+    comments: Vec<Comment>,
     lox: &'a mut Lox,
 }
 
 impl<'a> Scanner<'a> {
-    pub fn new(lox: &'a mut Lox, src: &'a String) -> Self {
+    pub fn new(lox: &'a mut Lox, src: &'a str) -> Self {
+        Self::for_file(lox, src, 0)
+    }
+
+    //The file id is copied into every span, so errors can tell
+    //which source they come from when there is more than one
+    pub fn for_file(lox: &'a mut Lox, src: &'a str, file: usize) -> Self {
         Self {
             source: src.chars().peekable(),
+            file,
             start: 0,
-            line: 0,
+            start_line: 1,
+            start_column: 1,
             current: 0,
+            line: 1,
+            column: 1,
             tokens: Vec::new(),
+            comments: Vec::new(),
             lox,
         }
     }
@@ -36,13 +55,18 @@ impl<'a> Scanner<'a> {
     pub fn scan_tokens(&mut self) -> &Vec<Token> {
         while !self.is_at_end() {
             self.start = self.current;
+            self.start_line = self.line;
+            self.start_column = self.column;
             self.scan_token();
         }
 
+        self.start = self.current;
+        self.start_line = self.line;
+        self.start_column = self.column;
         self.tokens.push(Token {
             token_type: TokenType::EOF,
             lexeme: "".to_string(),
-            line: self.line,
+            span: self.span(),
         });
         &self.tokens
     }
@@ -56,10 +80,12 @@ impl<'a> Scanner<'a> {
             '}' => self.add_token_from_char(TokenType::RIGHTBRACE, c),
             ',' => self.add_token_from_char(TokenType::COMMA, c),
             '.' => self.add_token_from_char(TokenType::DOT, c),
-            '-' => self.add_token_from_char(TokenType::MINUS, c),
-            '+' => self.add_token_from_char(TokenType::PLUS, c),
+            '-' => self.add_operator(c, TokenType::MINUS, TokenType::MINUSEQUAL),
+            '+' => self.add_operator(c, TokenType::PLUS, TokenType::PLUSEQUAL),
             ';' => self.add_token_from_char(TokenType::SEMICOLON, c),
-            '*' => self.add_token_from_char(TokenType::STAR, c),
+            '?' => self.add_token_from_char(TokenType::QUESTION, c),
+            ':' => self.add_token_from_char(TokenType::COLON, c),
+            '*' => self.add_operator(c, TokenType::STAR, TokenType::STAREQUAL),
             '!' => {
                 let mut tt = TokenType::BANG;
                 let mut lx = c.clone().to_string();
@@ -104,11 +130,11 @@ impl<'a> Scanner<'a> {
                 } else if self.match_next('*') {
                     self.handle_multiline_comment();
                 } else {
-                    self.add_token_from_char(TokenType::SLASH, c);
+                    self.add_operator(c, TokenType::SLASH, TokenType::SLASHEQUAL);
                 }
             }
             ' ' | '\t' | '\r' => {}
-            '\n' => self.line += 1,
+            '\n' => {}
             '"' => self.handle_strings(),
             _ => {
                 if c.is_digit(10) {
@@ -120,22 +146,41 @@ impl<'a> Scanner<'a> {
                     //todo: Implement a correct latin character matcher
                     self.handle_identifier(c);
                 } else {
-                    self.lox.error(self.line, "Unexpected character");
+                    self.lox.error(self.span(), "Unexpected character");
                 }
             }
         }
     }
 
     fn add_token(&mut self, tt: TokenType, lexeme: String) {
-        self.tokens.push(Token::new(tt, lexeme, self.line));
+        self.tokens.push(Token::new(tt, lexeme, self.span()));
     }
 
     fn add_token_from_char(&mut self, tt: TokenType, c: char) {
-        self.tokens.push(Token::new(tt, c.to_string(), self.line));
+        self.tokens.push(Token::new(tt, c.to_string(), self.span()));
+    }
+
+    //An operator that becomes a compound assignment when followed by =, ie. + and +=
+    fn add_operator(&mut self, c: char, operator: TokenType, compound: TokenType) {
+        match self.match_next('=') {
+            true => self.add_token(compound, format!("{}=", c)),
+            false => self.add_token_from_char(operator, c),
+        }
+    }
+
+    //From the start of the current token to the last consumed char
+    fn span(&self) -> Span {
+        Span::new(
+            self.file,
+            self.start,
+            self.current,
+            self.start_line,
+            self.start_column,
+        )
     }
 
     fn match_next(&mut self, expected: char) -> bool {
-        if self.is_at_end() || *self.source.peek().unwrap() == expected {
+        if self.source.peek() == Some(&expected) {
             self.advance();
             return true;
         } else {
@@ -143,9 +188,20 @@ impl<'a> Scanner<'a> {
         }
     }
 
+    //Every consumed char goes through here, so the position is always in sync.
+    //Callers check is_at_end before, past the end a \0 is returned
     fn advance(&mut self) -> char {
-        self.current += 1;
-        self.source.next().unwrap()
+        let Some(c) = self.source.next() else {
+            return '\0';
+        };
+        self.current += c.len_utf8();
+        if c == '\n' {
+            self.line += 1;
+            self.column = 1;
+        } else {
+            self.column += 1;
+        }
+        c
     }
 
     fn is_at_end(&mut self) -> bool {
@@ -154,29 +210,39 @@ impl<'a> Scanner<'a> {
 
     fn handle_comment(&mut self) {
         //Comment will span until the end of line
+        let mut text = String::from("//");
         while self.source.peek() != Some(&'\n') && !self.is_at_end() {
-            self.advance();
+            text.push(self.advance());
         }
+        self.add_comment(text.trim_end().to_string());
     }
 
+    //The opening /* is already consumed. Comments are not nested,
+    //the first */ closes the comment
     fn handle_multiline_comment(&mut self) {
-        //todo: Something arround here is broken
-        let next_token = self.source.peek().unwrap().clone();
-
-        while next_token != '*' {
-            if next_token == '\n' {
-                self.line += 1;
+        let mut text = String::from("/*");
+        while !self.is_at_end() {
+            let c = self.advance();
+            text.push(c);
+            if c == '*' && self.match_next('/') {
+                text.push('/');
+                self.add_comment(text);
+                return;
             }
-            self.advance();
         }
+        self.lox.error(self.span(), "Unterminated comment");
+    }
 
-        let next_next_token = self.source.peek().unwrap().clone();
-        while next_next_token != '/' {
-            if next_token == '\n' {
-                self.line += 1;
-            }
-            self.advance();
-        }
+    fn add_comment(&mut self, text: String) {
+        self.comments.push(Comment {
+            text,
+            span: self.span(),
+        });
+    }
+
+    //Every comment found by scan_tokens, in the order they appear
+    pub fn comments(&self) -> &Vec<Comment> {
+        &self.comments
     }
 
     fn handle_strings(&mut self) {
@@ -185,15 +251,14 @@ impl<'a> Scanner<'a> {
         'find_string: while let Some(c) = self.source.peek() {
             match c {
                 '"' => break 'find_string,
-                '\n' => {
-                    self.line += 1;
-                }
+                '\n' => {}
                 _ => cleaned_string += &c.clone().to_string(), // Just ignore the chars until the end of string
             }
             self.advance();
         }
 
         if self.is_at_end() {
+            self.lox.error(self.span(), "Unterminated string");
             return;
         }
         //Jump the "
@@ -206,7 +271,7 @@ impl<'a> Scanner<'a> {
 
     fn handle_numbers(&mut self, first_number: char) {
         let mut number = String::from(first_number);
-        let int = self.consume_while(|x| x.is_numeric());
+        let int = self.consume_while(|x| x.is_ascii_digit());
         number += &int.to_string();
         //If the int part encounters a non numeric char will end
         //If the value is ".", that means there is a float number.
@@ -215,14 +280,17 @@ impl<'a> Scanner<'a> {
         //If there is a point, position over that one
         if self.source.peek() == Some(&'.') {
             self.advance();
-            if self.source.peek().is_some() && self.source.peek().unwrap().is_numeric() {
-                let decimal = self.consume_while(|x| x.is_numeric());
+            if self.source.peek().is_some_and(|c| c.is_ascii_digit()) {
+                let decimal = self.consume_while(|x| x.is_ascii_digit());
                 number += ".";
                 number += &decimal.to_string();
             }
         }
 
-        self.add_token(TokenType::NUMBER(number.parse::<f64>().unwrap()), number);
+        match number.parse::<f64>() {
+            Ok(n) => self.add_token(TokenType::NUMBER(n), number),
+            Err(_) => self.lox.error(self.span(), "Invalid number"),
+        }
     }
 
     //Will consume
@@ -245,13 +313,11 @@ impl<'a> Scanner<'a> {
     //keyword token
     fn handle_identifier(&mut self, first_letter: char) {
         let mut kwlexeme = String::from(first_letter);
-        loop {
-            let c = self.source.peek();
-            if c.is_none() || !c.unwrap().is_alphanumeric() {
+        while let Some(c) = self.source.peek() {
+            if !c.is_alphanumeric() {
                 break;
             }
-            kwlexeme += &c.unwrap().clone().to_string();
-
+            kwlexeme.push(*c);
             self.advance();
         }
 
@@ -281,6 +347,7 @@ impl<'a> Scanner<'a> {
         keywords.insert("this", TokenType::THIS);
         keywords.insert("true", TokenType::TRUE);
         keywords.insert("var", TokenType::VAR);
+        keywords.insert("const", TokenType::CONST);
         keywords.insert("while", TokenType::WHILE);
         match keywords.get(identifier) {
             None => None,
@@ -293,11 +360,83 @@ impl<'a> Scanner<'a> {
 
 mod scanner_test {
     use crate::{
-        ast::token::{Token, TokenType},
-        lox::Lox,
+        ast::{
+            span::Span,
+            token::{Token, TokenType},
+        },
+        lox::{Lox, LoxError},
     };
 
     use super::Scanner;
+
+    //Spans for sources of a single line
+    fn span(start: usize, end: usize) -> Span {
+        Span::new(0, start, end, 1, start + 1)
+    }
+
+    #[test]
+    fn tracks_lines_and_columns() {
+        let src = String::from("var a;\n  \"b\nc\" ;");
+        let mut lox = Lox::default();
+        let mut scanner = Scanner::new(&mut lox, &src);
+        let tokens = scanner.scan_tokens();
+        let spans: Vec<Span> = tokens.iter().map(|t| t.span).collect();
+        assert_eq!(
+            spans,
+            vec![
+                Span::new(0, 0, 3, 1, 1),
+                Span::new(0, 4, 5, 1, 5),
+                Span::new(0, 5, 6, 1, 6),
+                Span::new(0, 9, 14, 2, 3),
+                Span::new(0, 15, 16, 3, 4),
+                Span::new(0, 16, 16, 3, 5),
+            ]
+        );
+    }
+
+    #[test]
+    fn multiline_comments() {
+        let src = String::from("/* a * b\n / c */ 1 /**/;");
+        let mut lox = Lox::default();
+        let mut scanner = Scanner::new(&mut lox, &src);
+        let tokens = scanner.scan_tokens();
+        let types: Vec<TokenType> = tokens.iter().map(|t| t.token_type.clone()).collect();
+        assert_eq!(
+            types,
+            vec![TokenType::NUMBER(1.0), TokenType::SEMICOLON, TokenType::EOF]
+        );
+        assert_eq!(tokens[0].line(), 2);
+
+        let comments: Vec<(&str, usize, usize)> = scanner
+            .comments()
+            .iter()
+            .map(|c| (c.text.as_str(), c.span.start, c.span.line))
+            .collect();
+        assert_eq!(comments, vec![("/* a * b\n / c */", 0, 1), ("/**/", 19, 2)]);
+    }
+
+    #[test]
+    fn unterminated_literals_are_errors() {
+        for src in ["/* never closed *", "\"never closed"] {
+            let mut lox = Lox::default();
+            match lox.eval(src) {
+                Err(LoxError::Scan(errors)) => {
+                    assert!(errors[0].message.starts_with("Unterminated"))
+                }
+                r => panic!("Expected a scan error, got {:?}", r),
+            }
+        }
+    }
+
+    #[test]
+    fn long_sources_do_not_overflow() {
+        let src = "1;\n".repeat(400);
+        let mut lox = Lox::default();
+        let mut scanner = Scanner::new(&mut lox, &src);
+        let tokens = scanner.scan_tokens();
+        assert_eq!(tokens.last().unwrap().line(), 401);
+    }
+
     #[test]
     fn boolean_last_tkn() {
         let src = String::from("true;");
@@ -308,17 +447,17 @@ mod scanner_test {
             Token {
                 token_type: TokenType::TRUE,
                 lexeme: "true".to_string(),
-                line: 0,
+                span: span(0, 4),
             },
             Token {
                 token_type: TokenType::SEMICOLON,
                 lexeme: ";".to_string(),
-                line: 0,
+                span: span(4, 5),
             },
             Token {
                 token_type: TokenType::EOF,
                 lexeme: "".to_string(),
-                line: 0,
+                span: span(5, 5),
             },
         ];
         assert_eq!(tokens, expected)
@@ -333,12 +472,12 @@ mod scanner_test {
             Token {
                 token_type: TokenType::NUMBER(3.2),
                 lexeme: "3.2".to_string(),
-                line: 0,
+                span: span(0, 3),
             },
             Token {
                 token_type: TokenType::EOF,
                 lexeme: "".to_string(),
-                line: 0,
+                span: span(3, 3),
             },
         ];
         assert_eq!(tokens, expected);
@@ -354,12 +493,12 @@ mod scanner_test {
             Token {
                 token_type: TokenType::STRING("Im a simple string".to_string()),
                 lexeme: "Im a simple string".to_string(),
-                line: 0,
+                span: span(0, 20),
             },
             Token {
                 token_type: TokenType::EOF,
                 lexeme: "".to_string(),
-                line: 0,
+                span: span(20, 20),
             },
         ];
         assert_eq!(tokens, expected);
@@ -375,7 +514,7 @@ mod scanner_test {
         let expected = &vec![Token {
             token_type: TokenType::EOF,
             lexeme: "".to_string(),
-            line: 0,
+            span: span(16, 16),
         }];
         assert_eq!(tokens, expected);
     }
@@ -390,22 +529,46 @@ mod scanner_test {
             Token {
                 token_type: TokenType::BANG,
                 lexeme: "!".to_string(),
-                line: 0,
+                span: span(0, 1),
             },
             Token {
                 token_type: TokenType::NUMBER(3.0),
                 lexeme: "3".to_string(),
-                line: 0,
+                span: span(1, 2),
             },
             Token {
                 token_type: TokenType::EOF,
                 lexeme: "".to_string(),
-                line: 0,
+                span: span(2, 2),
             },
         ];
         assert_eq!(tokens, expected);
     }
 
+    #[test]
+    fn compound_assignment_operators() {
+        let src = String::from("+= -=*= /= + =");
+        let mut lox = Lox::default();
+        let mut scanner = Scanner::new(&mut lox, &src);
+        let tokens = scanner.scan_tokens();
+        let lexemes: Vec<(TokenType, &str)> = tokens
+            .iter()
+            .map(|t| (t.token_type.clone(), t.lexeme.as_str()))
+            .collect();
+        assert_eq!(
+            lexemes,
+            vec![
+                (TokenType::PLUSEQUAL, "+="),
+                (TokenType::MINUSEQUAL, "-="),
+                (TokenType::STAREQUAL, "*="),
+                (TokenType::SLASHEQUAL, "/="),
+                (TokenType::PLUS, "+"),
+                (TokenType::EQUAL, "="),
+                (TokenType::EOF, ""),
+            ]
+        );
+    }
+
     #[test]
     fn scan_bang_eq() {
         let src = String::from("3!=4");
@@ -416,22 +579,22 @@ mod scanner_test {
             Token {
                 token_type: TokenType::NUMBER(3.0),
                 lexeme: "3".to_string(),
-                line: 0,
+                span: span(0, 1),
             },
             Token {
                 token_type: TokenType::BANGEQUAL,
                 lexeme: "!=".to_string(),
-                line: 0,
+                span: span(1, 3),
             },
             Token {
                 token_type: TokenType::NUMBER(4.0),
                 lexeme: "4".to_string(),
-                line: 0,
+                span: span(3, 4),
             },
             Token {
                 token_type: TokenType::EOF,
                 lexeme: "".to_string(),
-                line: 0,
+                span: span(4, 4),
             },
         ];
         assert_eq!(tokens, expected);
@@ -446,22 +609,22 @@ mod scanner_test {
             Token {
                 token_type: TokenType::NUMBER(3.0),
                 lexeme: "3".to_string(),
-                line: 0,
+                span: span(0, 1),
             },
             Token {
                 token_type: TokenType::GREATER,
                 lexeme: ">".to_string(),
-                line: 0,
+                span: span(1, 2),
             },
             Token {
                 token_type: TokenType::NUMBER(4.0),
                 lexeme: "4".to_string(),
-                line: 0,
+                span: span(2, 3),
             },
             Token {
                 token_type: TokenType::EOF,
                 lexeme: "".to_string(),
-                line: 0,
+                span: span(3, 3),
             },
         ];
         assert_eq!(tokens, expected);
@@ -0,0 +1,38 @@
+//Location of a piece of source code.
+//The byte range is used to slice the source, while line and column
+//(Both starting at 1) are the ones shown to the user
+#[derive(PartialEq, Eq, Debug, Clone, Copy, Default)]
+pub struct Span {
+    pub file: usize,  //Id of the source, given by whoever runs the scanner
+    pub start: usize, //First byte
+    pub end: usize,   //One past the last byte
+    pub line: usize,
+    pub column: usize,
+}
+
+impl Span {
+    pub fn new(file: usize, start: usize, end: usize, line: usize, column: usize) -> Self {
+        Self {
+            file,
+            start,
+            end,
+            line,
+            column,
+        }
+    }
+
+    //Smallest span covering both. Line and column are the ones of the first
+    pub fn merge(&self, other: &Span) -> Span {
+        let (first, last) = match self.start <= other.start {
+            true => (self, other),
+            false => (other, self),
+        };
+        Span {
+            file: first.file,
+            start: first.start,
+            end: last.end.max(first.end),
+            line: first.line,
+            column: first.column,
+        }
+    }
+}
@@ -1,10 +1,89 @@
-use super::{expr::Expr, token::Token};
+use std::rc::Rc;
+
+use super::{expr::Expr, span::Span, token::Token};
+
+//As with expressions, the span covers the whole statement
 #[derive(Debug)]
-pub enum Stmt {
+pub struct Stmt {
+    pub kind: StmtKind,
+    pub span: Span,
+}
+
+impl Stmt {
+    pub fn new(kind: StmtKind, span: Span) -> Self {
+        Self { kind, span }
+    }
+}
+
+//Same as the expressions, a long else if chain or deeply nested blocks
+//are dropped in a loop
+impl Drop for Stmt {
+    fn drop(&mut self) {
+        let mut pending = Vec::new();
+        self.kind.take_children(&mut pending);
+        while let Some(mut s) = pending.pop() {
+            s.kind.take_children(&mut pending);
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum StmtKind {
     PRINT(Box<Expr>),
     EXPR(Box<Expr>),
-    VAR(Box<Token>, Option<Box<Expr>>), //Variable (This token contains tt=declaration), declaration
+    VAR(Box<Token>, Option<Box<Token>>, Option<Box<Expr>>), //Variable (This token contains tt=declaration), type, declaration
+    CONST(Box<Token>, Option<Box<Token>>, Box<Expr>), //name, type, value | Like a var that can not be assigned again
     BLOCK(Vec<Box<Stmt>>), //The block is literally the content within some brackets. It has its own scope
     IF(Box<Expr>, Box<Stmt>, Option<Box<Stmt>>), //Condition, main body block (It is a block), else block
     WHILE(Box<Expr>, Box<Stmt>),                 //Loop condition, body (It is in fact a block)
+    FUNCTION(Rc<FunctionDecl>), //The declaration is shared with the runtime function object
+    RETURN(Box<Token>, Option<Box<Expr>>), //return keyword (For error reporting), returned value
+    CLASS(Box<Token>, Option<Box<Expr>>, Vec<Rc<FunctionDecl>>), //name, superclass (A variable expr), methods
+}
+
+impl StmtKind {
+    //Moves the inner statements out, the node is left as an empty block
+    fn take_children(&mut self, out: &mut Vec<Stmt>) {
+        match std::mem::replace(self, StmtKind::BLOCK(Vec::new())) {
+            StmtKind::BLOCK(stmts) => out.extend(stmts.into_iter().map(|s| *s)),
+            StmtKind::IF(_, then, else_) => {
+                out.push(*then);
+                out.extend(else_.map(|s| *s));
+            }
+            StmtKind::WHILE(_, body) => out.push(*body),
+            _ => {}
+        }
+    }
+}
+
+//The declaration outlives the statement list it was parsed in (ie. a function
+//defined in one line of the prompt and called in the next one). Thats why it
+//is behind an Rc, the runtime function object keeps its own reference to it
+#[derive(Debug)]
+pub struct FunctionDecl {
+    pub name: Token,
+    pub params: Vec<Token>,
+    pub param_types: Vec<Option<Token>>, //One for every param, None if it has no type
+    pub return_type: Option<Token>,
+    pub body: Vec<Box<Stmt>>,
+    pub close: Span, //Closing brace of the body, where a function without return ends
+}
+
+impl FunctionDecl {
+    //The params as written in the source, ie. a: number
+    pub fn typed_params(&self) -> Vec<String> {
+        self.params
+            .iter()
+            .zip(self.param_types.iter())
+            .map(|(param, type_)| typed_name(param, type_.as_ref()))
+            .collect()
+    }
+}
+
+//name: type, or only the name when there is no type
+pub fn typed_name(name: &Token, type_: Option<&Token>) -> String {
+    match type_ {
+        Some(type_) => format!("{}: {}", name.lexeme, type_.lexeme),
+        None => name.lexeme.clone(),
+    }
 }
@@ -1,4 +1,6 @@
 use std::fmt::Debug;
+
+use super::span::Span;
 #[derive(Debug, PartialEq, Clone)]
 #[allow(dead_code)]
 #[warn(non_camel_case_types)]
@@ -12,8 +14,10 @@ pub enum TokenType {
     MINUS, //Sub
     PLUS,  //Sum
     SEMICOLON,
-    SLASH, //Division
-    STAR,  //Product
+    SLASH,    //Division
+    STAR,     //Product
+    QUESTION, //Conditional, cond ? a : b
+    COLON,
 
     // One or two character tokens.
     BANG, //Negation
@@ -24,6 +28,10 @@ pub enum TokenType {
     GREATEREQUAL,
     LESS,
     LESSEQUAL,
+    PLUSEQUAL, //Compound assignments, a += 1
+    MINUSEQUAL,
+    STAREQUAL,
+    SLASHEQUAL,
 
     // Literals.
     IDENTIFIER(String), //var x =  This Is The Name Of A Variable
@@ -33,6 +41,7 @@ pub enum TokenType {
     // Keywords.
     AND,
     CLASS,
+    CONST,
     ELSE,
     FALSE,
     FUN,
@@ -62,6 +71,16 @@ impl TokenType {
             _ => self == other,
         }
     }
+
+    //The type without the internal value, IDENTIFIER instead of IDENTIFIER("a")
+    pub fn name(&self) -> String {
+        match self {
+            TokenType::IDENTIFIER(_) => String::from("IDENTIFIER"),
+            TokenType::STRING(_) => String::from("STRING"),
+            TokenType::NUMBER(_) => String::from("NUMBER"),
+            _ => format!("{:?}", self),
+        }
+    }
 }
 
 //Literal is the current value of the token.
@@ -71,24 +90,36 @@ impl TokenType {
 pub struct Token {
     pub token_type: TokenType, //The type + the value in some cases
     pub lexeme: String,        //The substring for the token
-    pub line: u8,              //Line where the token appears
+    pub span: Span,            //Where the token appears
 }
 
 impl Token {
-    pub fn new(token_type: TokenType, lexeme: String, line: u8) -> Self {
+    pub fn new(token_type: TokenType, lexeme: String, span: Span) -> Self {
         Self {
             token_type,
             lexeme,
-            line,
+            span,
         }
     }
+
+    pub fn line(&self) -> usize {
+        self.span.line
+    }
+}
+
+//Comments are not tokens, the parser never sees them. The scanner keeps
+//them apart (as trivia) so tools like the formatter can put them back
+#[derive(PartialEq, Debug, Clone)]
+pub struct Comment {
+    pub text: String, //With the // or the /* */
+    pub span: Span,
 }
 
 impl ToString for Token {
     fn to_string(&self) -> String {
         String::from(format!(
-            "{:?} - {} - {}",
-            self.token_type, self.lexeme, self.line
+            "{:?} - {} - {}:{}",
+            self.token_type, self.lexeme, self.span.line, self.span.column
         ))
     }
 }
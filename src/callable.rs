@@ -0,0 +1,113 @@
+use std::{
+    fmt::{Debug, Display},
+    rc::Rc,
+};
+
+use crate::{
+    ast::stmt::FunctionDecl,
+    class::Instance,
+    enviroment::Environment,
+    interpreter::{Interpreter, RuntimeError},
+    object::Object,
+};
+
+pub const THIS: &str = "this";
+
+//Anything that can be used as callee in a call expression
+pub trait Callable {
+    //Shown in the traceback of the runtime errors
+    fn name(&self) -> String;
+    fn arity(&self) -> usize;
+    fn call(
+        &self,
+        interpreter: &mut Interpreter,
+        arguments: Vec<Object>,
+    ) -> Result<Object, RuntimeError>;
+}
+
+//Runtime representation of a function declared with `fun`.
+//Cloning it is cheap, only the Rc to the declaration and the handle
+//to the captured scope are cloned
+#[derive(Clone)]
+pub struct Function {
+    declaration: Rc<FunctionDecl>,
+    //Scope where the function was declared. It keeps alive every variable
+    //visible at that point, even after the declaring block has ended
+    closure: Environment,
+    //The init method of a class always returns the instance
+    is_initializer: bool,
+}
+
+impl Function {
+    pub fn new(declaration: Rc<FunctionDecl>, closure: Environment, is_initializer: bool) -> Self {
+        Self {
+            declaration,
+            closure,
+            is_initializer,
+        }
+    }
+
+    //Creates a copy of the method whose closure has `this` defined
+    //as the received instance. The resolver counts that extra scope
+    pub fn bind(&self, instance: Instance) -> Function {
+        let mut scope = self.closure.new_enclosed();
+        //Defining in a fresh scope can not fail
+        _ = scope.define(&THIS.to_string(), Some(Object::InstanceObj(instance)));
+        Function::new(self.declaration.clone(), scope, self.is_initializer)
+    }
+}
+
+impl Callable for Function {
+    fn name(&self) -> String {
+        self.declaration.name.lexeme.clone()
+    }
+
+    fn arity(&self) -> usize {
+        self.declaration.params.len()
+    }
+
+    fn call(
+        &self,
+        interpreter: &mut Interpreter,
+        arguments: Vec<Object>,
+    ) -> Result<Object, RuntimeError> {
+        let result = interpreter.execute_function(&self.declaration, &self.closure, arguments)?;
+        if self.is_initializer {
+            return self
+                .closure
+                .get_at(0, &THIS.to_string())
+                .ok_or(RuntimeError::UndefinedVariable(THIS.to_string()));
+        }
+        Ok(result)
+    }
+}
+
+//Two functions are equal only if they come from the same declaration and
+//captured the same scope. Each call to makeCounter() makes a new closure,
+//and each access to a method binds it to its instance in a new scope
+impl PartialEq for Function {
+    fn eq(&self, other: &Self) -> bool {
+        Rc::ptr_eq(&self.declaration, &other.declaration) && self.closure.ptr_eq(&other.closure)
+    }
+}
+
+//There is no order between functions
+impl PartialOrd for Function {
+    fn partial_cmp(&self, _other: &Self) -> Option<std::cmp::Ordering> {
+        None
+    }
+}
+
+//The closure is left out on purpose. A function is usually defined inside the
+//scope it captures, so printing it would never end
+impl Debug for Function {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Function({})", self.declaration.name.lexeme)
+    }
+}
+
+impl Display for Function {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "<fn {}>", self.declaration.name.lexeme)
+    }
+}
@@ -0,0 +1,171 @@
+use std::{
+    cell::RefCell,
+    collections::HashMap,
+    fmt::{Debug, Display},
+    rc::Rc,
+};
+
+use crate::{
+    ast::token::Token,
+    callable::{Callable, Function},
+    interpreter::{Interpreter, RuntimeError},
+    object::Object,
+};
+
+//The initializer is just a method with this name
+pub const INITIALIZER: &str = "init";
+
+//Name of the variable that holds the superclass inside subclass methods
+pub const SUPER: &str = "super";
+
+//Runtime representation of a class declaration.
+//It is always shared behind an Rc, every instance points to its class
+pub struct Class {
+    name: String,
+    superclass: Option<Rc<Class>>,
+    methods: HashMap<String, Function>,
+}
+
+impl Class {
+    pub fn new(
+        name: String,
+        superclass: Option<Rc<Class>>,
+        methods: HashMap<String, Function>,
+    ) -> Self {
+        Self {
+            name,
+            superclass,
+            methods,
+        }
+    }
+
+    //If the class does not define the method, it is inherited from the superclass chain
+    pub fn find_method(&self, name: &str) -> Option<&Function> {
+        match self.methods.get(name) {
+            Some(method) => Some(method),
+            None => self.superclass.as_ref()?.find_method(name),
+        }
+    }
+}
+
+//Calling a class creates a new instance. If the class has an initializer,
+//it is called with the received arguments before returning the instance
+impl Callable for Rc<Class> {
+    fn name(&self) -> String {
+        self.name.clone()
+    }
+
+    fn arity(&self) -> usize {
+        match self.find_method(INITIALIZER) {
+            Some(init) => init.arity(),
+            None => 0,
+        }
+    }
+
+    fn call(
+        &self,
+        interpreter: &mut Interpreter,
+        arguments: Vec<Object>,
+    ) -> Result<Object, RuntimeError> {
+        let instance = Instance::new(self.clone());
+        if let Some(init) = self.find_method(INITIALIZER) {
+            init.bind(instance.clone()).call(interpreter, arguments)?;
+        }
+        Ok(Object::InstanceObj(instance))
+    }
+}
+
+//Instances are handles, like environments. Cloning one does not copy the
+//fields, so every variable holding the instance sees the same state
+#[derive(Clone)]
+pub struct Instance {
+    inner: Rc<RefCell<InstanceInner>>,
+}
+
+struct InstanceInner {
+    class: Rc<Class>,
+    fields: HashMap<String, Object>,
+}
+
+impl Instance {
+    pub fn new(class: Rc<Class>) -> Self {
+        Self {
+            inner: Rc::new(RefCell::new(InstanceInner {
+                class,
+                fields: HashMap::new(),
+            })),
+        }
+    }
+
+    //Fields shadow methods. A method is returned bound to this instance,
+    //so it can be stored and called later keeping the right `this`
+    pub fn get(&self, name: &Token) -> Result<Object, RuntimeError> {
+        let inner = self.inner.borrow();
+        if let Some(v) = inner.fields.get(&name.lexeme) {
+            return Ok(v.clone());
+        }
+
+        match inner.class.find_method(&name.lexeme) {
+            Some(method) => Ok(Object::FunctionObj(method.bind(self.clone()))),
+            None => Err(RuntimeError::UndefinedProperty(name.lexeme.clone())),
+        }
+    }
+
+    pub fn set(&self, name: &Token, value: Object) {
+        self.inner
+            .borrow_mut()
+            .fields
+            .insert(name.lexeme.clone(), value);
+    }
+}
+
+//Classes and instances are compared by identity
+impl PartialEq for Class {
+    fn eq(&self, other: &Self) -> bool {
+        std::ptr::eq(self, other)
+    }
+}
+
+impl PartialOrd for Class {
+    fn partial_cmp(&self, _other: &Self) -> Option<std::cmp::Ordering> {
+        None
+    }
+}
+
+impl PartialEq for Instance {
+    fn eq(&self, other: &Self) -> bool {
+        Rc::ptr_eq(&self.inner, &other.inner)
+    }
+}
+
+impl PartialOrd for Instance {
+    fn partial_cmp(&self, _other: &Self) -> Option<std::cmp::Ordering> {
+        None
+    }
+}
+
+//As with functions, the content is left out. An instance can hold itself
+//in one of its fields
+impl Debug for Class {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Class({})", self.name)
+    }
+}
+
+impl Debug for Instance {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Instance({})", self.inner.borrow().class.name)
+    }
+}
+
+impl Display for Class {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.name)
+    }
+}
+
+impl Display for Instance {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} instance", self.inner.borrow().class.name)
+    }
+}
@@ -0,0 +1,371 @@
+use std::fmt::Write;
+
+use crate::{
+    ast::{parser::ParserError, span::Span},
+    interpreter::{RuntimeError, RuntimeFailure},
+    lox::ScanError,
+    resolver::ResolverError,
+    typechecker::TypeCheckError,
+};
+
+//ANSI escape codes. Only used when the renderer is in colored mode
+const RESET: &str = "\x1b[0m";
+const BOLD_RED: &str = "\x1b[1;31m";
+const BOLD_BLUE: &str = "\x1b[1;34m";
+const BOLD: &str = "\x1b[1m";
+
+//An error ready to be shown to the user. The code is short and stable,
+//so it can be searched for. Only a runtime error raised outside of any
+//node (ie. by the interpreter itself) can be left without a location
+#[derive(Debug, PartialEq, Clone)]
+pub struct Diagnostic {
+    pub code: &'static str,
+    pub message: String,
+    pub span: Option<Span>,
+    pub help: Vec<String>,
+    //Calls that were running when a runtime error happened, the most recent first
+    pub trace: Vec<String>,
+}
+
+impl Diagnostic {
+    pub fn new(code: &'static str, message: String) -> Self {
+        Self {
+            code,
+            message,
+            span: None,
+            help: Vec::new(),
+            trace: Vec::new(),
+        }
+    }
+
+    pub fn at(mut self, span: Span) -> Self {
+        self.span = Some(span);
+        self
+    }
+
+    pub fn with_help(mut self, note: &str) -> Self {
+        self.help.push(note.to_string());
+        self
+    }
+
+    pub fn with_trace(mut self, trace: Vec<String>) -> Self {
+        self.trace = trace;
+        self
+    }
+}
+
+//Turns diagnostics into text like:
+//
+//  error[E0100]: Unexpected token - Expected SEMICOLON, got NUMBER
+//   --> script.lox:2:9
+//    |
+//  2 | print 2 3;
+//    |         ^
+//    = help: every statement ends with a ;
+#[derive(Default)]
+pub struct Renderer {
+    color: bool,
+}
+
+impl Renderer {
+    pub fn plain() -> Self {
+        Self { color: false }
+    }
+
+    pub fn colored() -> Self {
+        Self { color: true }
+    }
+
+    //The source must be the one the span was taken from. If the span
+    //does not fit in it, the snippet is left out
+    pub fn render(&self, diagnostic: &Diagnostic, file_name: &str, source: &str) -> String {
+        let mut out = String::new();
+        _ = writeln!(
+            out,
+            "{}: {}",
+            self.paint(BOLD_RED, &format!("error[{}]", diagnostic.code)),
+            self.paint(BOLD, &diagnostic.message)
+        );
+
+        //The gutter is as wide as the line number
+        let mut gutter = 1;
+        if let Some(span) = diagnostic.span {
+            let line_number = span.line.to_string();
+            gutter = line_number.len();
+            _ = writeln!(
+                out,
+                "{:gutter$}{} {}:{}:{}",
+                "",
+                self.paint(BOLD_BLUE, "-->"),
+                file_name,
+                span.line,
+                span.column
+            );
+
+            if let Some((line, padding, width)) = snippet(source, &span) {
+                let bar = self.paint(BOLD_BLUE, "|");
+                _ = writeln!(out, "{:gutter$} {}", "", bar);
+                _ = writeln!(
+                    out,
+                    "{} {} {}",
+                    self.paint(BOLD_BLUE, &line_number),
+                    bar,
+                    line
+                );
+                _ = writeln!(
+                    out,
+                    "{:gutter$} {} {}{}",
+                    "",
+                    bar,
+                    padding,
+                    self.paint(BOLD_RED, &"^".repeat(width))
+                );
+            }
+        }
+
+        if !diagnostic.trace.is_empty() {
+            _ = writeln!(out, "traceback (most recent call first):");
+            for line in diagnostic.trace.iter() {
+                _ = writeln!(out, "    {}", line);
+            }
+        }
+
+        for note in diagnostic.help.iter() {
+            _ = writeln!(
+                out,
+                "{:gutter$} {} {}",
+                "",
+                self.paint(BOLD_BLUE, "="),
+                self.paint(BOLD, &format!("help: {}", note))
+            );
+        }
+        out
+    }
+
+    fn paint(&self, color: &str, text: &str) -> String {
+        match self.color {
+            true => format!("{}{}{}", color, text, RESET),
+            false => text.to_string(),
+        }
+    }
+}
+
+//The line where the span starts, the blank space before the span (tabs are
+//kept so the caret lines up) and the amount of carets. A span over several
+//lines is only underlined until the end of the first one
+fn snippet(source: &str, span: &Span) -> Option<(String, String, usize)> {
+    if span.end > source.len() {
+        return None;
+    }
+    let before = source.get(..span.start)?;
+    let line_start = before.rfind('\n').map(|i| i + 1).unwrap_or(0);
+    let line_end = source[span.start..]
+        .find('\n')
+        .map(|i| span.start + i)
+        .unwrap_or(source.len());
+    let line = source[line_start..line_end].trim_end_matches('\r');
+
+    let padding: String = source[line_start..span.start]
+        .chars()
+        .map(|c| if c == '\t' { '\t' } else { ' ' })
+        .collect();
+    let end = span.end.clamp(span.start, line_end);
+    let width = source.get(span.start..end)?.chars().count().max(1);
+    Some((line.to_string(), padding, width))
+}
+
+impl From<&ScanError> for Diagnostic {
+    fn from(e: &ScanError) -> Self {
+        Diagnostic::new("E0001", e.message.clone()).at(e.span)
+    }
+}
+
+impl From<&ParserError> for Diagnostic {
+    fn from(e: &ParserError) -> Self {
+        let diagnostic = Diagnostic::new(parser_code(e), e.message()).at(e.span());
+        match e {
+            ParserError::UnexpectedToken(_, _) => diagnostic,
+            ParserError::NonValidAssigmentTarget(_) => {
+                diagnostic.with_help("only variables and fields (obj.field) can be assigned")
+            }
+            ParserError::TooManyArguments(_) => {
+                diagnostic.with_help("group the values in an instance and pass it instead")
+            }
+            ParserError::TooDeeplyNested(_) => {
+                diagnostic.with_help("move the inner part to a variable or a function")
+            }
+        }
+    }
+}
+
+impl From<&ResolverError> for Diagnostic {
+    fn from(e: &ResolverError) -> Self {
+        let diagnostic = Diagnostic::new(resolver_code(e), e.message()).at(e.span());
+        match e {
+            ResolverError::ReadInOwnInitializer(_, _) => {
+                diagnostic.with_help("use another name for the new variable")
+            }
+            ResolverError::ReturnFromInitializer(_) => {
+                diagnostic.with_help("init always returns the instance, use an empty return")
+            }
+            ResolverError::AssignToConst(_, _) => {
+                diagnostic.with_help("declare it with var if it has to change")
+            }
+            ResolverError::TooDeeplyNested(_) => {
+                diagnostic.with_help("move the inner part to a variable or a function")
+            }
+            _ => diagnostic,
+        }
+    }
+}
+
+impl From<&TypeCheckError> for Diagnostic {
+    fn from(e: &TypeCheckError) -> Self {
+        let diagnostic = Diagnostic::new(type_code(e), e.message()).at(e.span());
+        match e {
+            TypeCheckError::UnknownType(_, _) => {
+                diagnostic.with_help("the types are number, string, boolean, nil and any")
+            }
+            TypeCheckError::Uninitialized(_, _, _) => {
+                diagnostic.with_help("give it a value, or leave the type out")
+            }
+            TypeCheckError::TooDeeplyNested(_) => {
+                diagnostic.with_help("move the inner part to a variable or a function")
+            }
+            _ => diagnostic,
+        }
+    }
+}
+
+impl From<&RuntimeError> for Diagnostic {
+    fn from(e: &RuntimeError) -> Self {
+        let diagnostic = Diagnostic::new(runtime_code(e), e.message());
+        match e {
+            RuntimeError::UndefinedVariable(_) => {
+                diagnostic.with_help("declare it with var before using it")
+            }
+            RuntimeError::ConstAssignment(_) => {
+                diagnostic.with_help("declare it with var if it has to change")
+            }
+            _ => diagnostic,
+        }
+    }
+}
+
+impl From<&RuntimeFailure> for Diagnostic {
+    fn from(failure: &RuntimeFailure) -> Self {
+        let mut diagnostic = Diagnostic::from(&failure.error);
+        //At top level the traceback would only repeat the location
+        if !failure.stack.is_empty() {
+            diagnostic = diagnostic.with_trace(failure.traceback());
+        }
+        match failure.span {
+            Some(span) => diagnostic.at(span),
+            None => diagnostic,
+        }
+    }
+}
+
+//Codes are grouped by stage: 00xx scanner, 01xx parser, 02xx resolver,
+//03xx runtime and 04xx type checker
+fn parser_code(e: &ParserError) -> &'static str {
+    match e {
+        ParserError::UnexpectedToken(_, _) => "E0100",
+        ParserError::NonValidAssigmentTarget(_) => "E0101",
+        ParserError::TooManyArguments(_) => "E0102",
+        ParserError::TooDeeplyNested(_) => "E0103",
+    }
+}
+
+fn resolver_code(e: &ResolverError) -> &'static str {
+    match e {
+        ResolverError::ReadInOwnInitializer(_, _) => "E0200",
+        ResolverError::AlreadyDeclared(_, _) => "E0201",
+        ResolverError::ReturnOutsideFunction(_) => "E0202",
+        ResolverError::ReturnFromInitializer(_) => "E0203",
+        ResolverError::ThisOutsideClass(_) => "E0204",
+        ResolverError::InheritFromItself(_, _) => "E0205",
+        ResolverError::SuperOutsideClass(_) => "E0206",
+        ResolverError::SuperWithoutSuperclass(_) => "E0207",
+        ResolverError::AssignToConst(_, _) => "E0208",
+        ResolverError::TooDeeplyNested(_) => "E0209",
+    }
+}
+
+fn type_code(e: &TypeCheckError) -> &'static str {
+    match e {
+        TypeCheckError::UnknownType(_, _) => "E0400",
+        TypeCheckError::InvalidOperands(_, _) => "E0401",
+        TypeCheckError::Mismatch(_, _, _, _) => "E0402",
+        TypeCheckError::Uninitialized(_, _, _) => "E0403",
+        TypeCheckError::TooDeeplyNested(_) => "E0404",
+    }
+}
+
+fn runtime_code(e: &RuntimeError) -> &'static str {
+    match e {
+        RuntimeError::TypeError(_) => "E0300",
+        RuntimeError::UnknownError => "E0301",
+        RuntimeError::UnknownExpression(_) => "E0302",
+        RuntimeError::UndefinedVariable(_) => "E0303",
+        RuntimeError::UndefinedProperty(_) => "E0304",
+        RuntimeError::ArityError(_) => "E0305",
+        RuntimeError::IoError(_) => "E0306",
+        RuntimeError::Return(_) => "E0307",
+        RuntimeError::StackOverflow => "E0308",
+        RuntimeError::ConstAssignment(_) => "E0309",
+    }
+}
+
+#[cfg(test)]
+mod diagnostics_tests {
+    use crate::ast::{parser::ParserError, span::Span};
+
+    use super::{Diagnostic, Renderer};
+
+    #[test]
+    fn plain_rendering() {
+        let source = "print 1;\nprint 2 3;";
+        let error = ParserError::UnexpectedToken(
+            String::from("Expected SEMICOLON"),
+            Span::new(0, 17, 18, 2, 9),
+        );
+        let rendered = Renderer::plain().render(&Diagnostic::from(&error), "test.lox", source);
+        assert_eq!(
+            rendered,
+            "error[E0100]: Unexpected token - Expected SEMICOLON\n \
+             --> test.lox:2:9\n  \
+             |\n\
+             2 | print 2 3;\n  \
+             |         ^\n"
+        );
+    }
+
+    #[test]
+    fn underline_and_help() {
+        let source = "  1 = 2;";
+        let error = ParserError::NonValidAssigmentTarget(Span::new(0, 2, 3, 1, 3));
+        let diagnostic = Diagnostic::from(&error);
+        let rendered = Renderer::plain().render(&diagnostic, "a.lox", source);
+        assert!(rendered.contains("1 |   1 = 2;\n  |   ^\n"));
+        assert!(rendered.contains("  = help: only variables"));
+
+        let wide = Diagnostic::new("E0000", String::from("wide")).at(Span::new(0, 2, 7, 1, 3));
+        let rendered = Renderer::plain().render(&wide, "a.lox", source);
+        assert!(rendered.contains("|   ^^^^^\n"));
+    }
+
+    #[test]
+    fn colored_rendering() {
+        let diagnostic = Diagnostic::new("E0303", String::from("Undefined variable a"));
+        let rendered = Renderer::colored().render(&diagnostic, "a.lox", "");
+        assert!(rendered.starts_with("\x1b[1;31merror[E0303]\x1b[0m"));
+    }
+
+    #[test]
+    fn span_outside_the_source() {
+        let diagnostic = Diagnostic::new("E0000", String::from("x")).at(Span::new(0, 40, 41, 3, 1));
+        let rendered = Renderer::plain().render(&diagnostic, "a.lox", "short");
+        assert_eq!(rendered, "error[E0000]: x\n --> a.lox:3:1\n");
+    }
+}
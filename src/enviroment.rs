@@ -1,5 +1,9 @@
 use crate::{interpreter::RuntimeError, object::Object};
-use std::{cell::RefCell, collections::HashMap, rc::Rc};
+use std::{
+    cell::RefCell,
+    collections::{HashMap, HashSet},
+    rc::Rc,
+};
 
 type VarContent = Object;
 type EnviromentResult = Result<VarContent, RuntimeError>;
@@ -7,46 +11,42 @@ type EnviromentOption = Option<VarContent>;
 #[derive(Debug, Default)]
 pub struct EnvironmentInner {
     locals: HashMap<String, VarContent>,
+    //Locals declared with const, they can not be assigned
+    consts: HashSet<String>,
+    //The scope where this one was created. None only for the global scope
+    enclosing: Option<Environment>,
 }
 
-//This is an ugly solution to the difficulties of building Linked Lists in rust
-#[derive(Debug, Clone)]
+//Each scope points to its parent, so any scope can be captured (ie. by a function)
+//and kept alive after the block that created it has ended.
+//Cloning an Environment does not copy the variables, it only creates a new
+//handle to the same scope. Thats what allows closures to share their state
+#[derive(Debug, Clone, Default)]
 pub struct Environment {
-    envs: Vec<Rc<RefCell<EnvironmentInner>>>,
-    curr: usize,
+    inner: Rc<RefCell<EnvironmentInner>>,
 }
 
 impl Environment {
     pub fn new() -> Self {
-        Self {
-            envs: Vec::from([Rc::new(RefCell::new(EnvironmentInner::default()))]),
-            curr: 0,
-        }
-    }
-
-    //Will create a new local env to the stack, and move the pointer to it.
-    //Any following variable operation will start with it
-    pub fn add_new_local(&mut self) -> Result<(), RuntimeError> {
-        let new_env = Rc::new(RefCell::new(EnvironmentInner::default()));
-        self.envs.push(new_env);
-        //The last one (The one at the top) is the innermost scope
-        self.curr = self.envs.len() - 1;
-        // print!("New local -> ");
-        // self.print_status();
-        Ok(())
+        Self::default()
     }
 
-    pub fn remove_local(&mut self) -> Result<(), RuntimeError> {
-        if self.envs.len() == 1 {
-            return Err(RuntimeError::ScopeError(Some(String::from(
-                "Cant remove the global scope",
-            ))));
+    //Creates a new scope whose parent is the current one.
+    //Any following variable operation on the new scope will start with it
+    //and, on fail, will try one level above (Until global)
+    pub fn new_enclosed(&self) -> Self {
+        Self {
+            inner: Rc::new(RefCell::new(EnvironmentInner {
+                locals: HashMap::new(),
+                consts: HashSet::new(),
+                enclosing: Some(self.clone()),
+            })),
         }
+    }
 
-        self.envs.pop();
-        //Reset the current scope to the innermost
-        self.curr = self.envs.len() - 1;
-        Ok(())
+    //Both handles point to the same scope
+    pub fn ptr_eq(&self, other: &Environment) -> bool {
+        Rc::ptr_eq(&self.inner, &other.inner)
     }
 
     pub fn define(&mut self, name: &String, value: Option<Object>) -> Result<(), RuntimeError> {
@@ -55,48 +55,120 @@ impl Environment {
             None => Object::NullObj,
         };
 
-        self.envs[self.curr]
-            .borrow_mut()
-            .locals
-            .insert(name.clone(), insertion_value);
-        // print!("Define variable -> ");
-        // self.print_status();
+        let mut inner = self.inner.borrow_mut();
+        //Declaring it again, as a var, makes it assignable
+        inner.consts.remove(name);
+        inner.locals.insert(name.clone(), insertion_value);
+        Ok(())
+    }
+
+    pub fn define_const(&mut self, name: &String, value: Object) -> Result<(), RuntimeError> {
+        self.define(name, Some(value))?;
+        self.inner.borrow_mut().consts.insert(name.clone());
         Ok(())
     }
 
+    //Returns the new value of the variable, because an assignment is an
+    //expression and that is its result
     pub fn assign(&mut self, name: &String, value: Object) -> EnviromentResult {
-        while self.curr < self.envs.len() {
-            let mut inner = self.envs[self.curr].borrow_mut();
-            match inner.locals.get(name) {
-                Some(_) => match inner.locals.insert(name.clone(), value) {
-                    None => return Err(RuntimeError::UndefinedVariable(name.clone())),
-                    Some(a) => {
-                        // self.print_status();
-                        return Ok(a);
-                    }
-                },
-                None => {
-                    if self.curr == 0 || self.envs.len() == 1 {
-                        return Err(RuntimeError::UndefinedVariable(name.clone()));
-                    }
-                    self.curr -= 1;
-                }
-            }
+        let mut inner = self.inner.borrow_mut();
+        if inner.consts.contains(name) {
+            return Err(RuntimeError::ConstAssignment(name.clone()));
+        }
+        if let Some(slot) = inner.locals.get_mut(name) {
+            *slot = value.clone();
+            return Ok(value);
+        }
+
+        match &mut inner.enclosing {
+            Some(parent) => parent.assign(name, value),
+            None => Err(RuntimeError::UndefinedVariable(name.clone())),
         }
-        return Err(RuntimeError::UndefinedVariable(name.clone()));
     }
+
     pub fn get(&self, name: &String) -> EnviromentOption {
-        let loc_curr = self.envs.len();
-        for l in (0..loc_curr).rev() {
-            // println!("Envs: {:?}", self.envs[l]);
-            if let Some(v) = self.envs[l].borrow().locals.get(name) {
-                return Some(v.clone());
+        let inner = self.inner.borrow();
+        if let Some(v) = inner.locals.get(name) {
+            return Some(v.clone());
+        }
+
+        match &inner.enclosing {
+            Some(parent) => parent.get(name),
+            None => None,
+        }
+    }
+
+    //Walks up the parents chain. The resolver already checked that the
+    //scope exists, so a missing one is an error in the interpreter itself
+    fn ancestor(&self, distance: usize) -> Result<Environment, RuntimeError> {
+        let mut env = self.clone();
+        for _ in 0..distance {
+            let parent = env.inner.borrow().enclosing.clone();
+            match parent {
+                Some(p) => env = p,
+                None => return Err(RuntimeError::UnknownError),
             }
         }
-        None
+        Ok(env)
+    }
+
+    //Same as get, but goes straight to the scope where the resolver found the variable
+    pub fn get_at(&self, distance: usize, name: &String) -> EnviromentOption {
+        let env = self.ancestor(distance).ok()?;
+        let value = env.inner.borrow().locals.get(name).cloned();
+        value
+    }
+
+    //Same as assign, but goes straight to the scope where the resolver found the variable
+    pub fn assign_at(&mut self, distance: usize, name: &String, value: Object) -> EnviromentResult {
+        let env = self.ancestor(distance)?;
+        let mut inner = env.inner.borrow_mut();
+        if inner.consts.contains(name) {
+            return Err(RuntimeError::ConstAssignment(name.clone()));
+        }
+        match inner.locals.get_mut(name) {
+            Some(slot) => {
+                *slot = value.clone();
+                Ok(value)
+            }
+            None => Err(RuntimeError::UndefinedVariable(name.clone())),
+        }
+    }
+}
+
+#[cfg(test)]
+mod enviroment_tests {
+    use crate::{interpreter::RuntimeError, object::Object};
+
+    use super::Environment;
+
+    #[test]
+    fn consts_can_not_be_assigned() {
+        let mut globals = Environment::new();
+        let name = "a".to_string();
+        globals.define_const(&name, Object::NumberObj(1.0)).unwrap();
+
+        //From nested scopes too, with and without the resolved depth
+        let mut inner = globals.new_enclosed().new_enclosed();
+        let error = Err(RuntimeError::ConstAssignment(name.clone()));
+        assert_eq!(inner.assign(&name, Object::NumberObj(2.0)), error);
+        assert_eq!(inner.assign_at(2, &name, Object::NumberObj(2.0)), error);
+        assert_eq!(inner.get(&name), Some(Object::NumberObj(1.0)));
+
+        //A var in between shadows it
+        let mut middle = globals.new_enclosed();
+        middle.define(&name, None).unwrap();
+        let mut inner = middle.new_enclosed();
+        assert!(inner.assign(&name, Object::NumberObj(3.0)).is_ok());
+        assert_eq!(middle.get(&name), Some(Object::NumberObj(3.0)));
     }
 
-    pub fn print_status(&self) {
-        println!("Current: {:?} | Variables: {:?}", self.curr, self.envs);
+    #[test]
+    fn declaring_again_makes_it_assignable() {
+        let mut globals = Environment::new();
+        let name = "a".to_string();
+        globals.define_const(&name, Object::NumberObj(1.0)).unwrap();
+        globals.define(&name, None).unwrap();
+        assert!(globals.assign(&name, Object::NumberObj(2.0)).is_ok());
     }
 }
@@ -0,0 +1,652 @@
+use crate::ast::{
+    expr::{Expr, ExprKind},
+    stmt::{typed_name, FunctionDecl, Stmt, StmtKind},
+    token::Comment,
+};
+
+const INDENT: &str = "  ";
+
+//Writes the source of a parsed program back in the canonical style: two
+//spaces of indentation, one statement per line, spaces around the binary
+//operators and no more than one blank line between statements.
+//
+//Comments are not part of the AST. They are put back between the
+//statements using their position in the source: a comment on the same
+//line as the end of a statement stays after it, any other one goes in
+//its own line before the statement that follows it. A comment in the
+//middle of a statement (ie. inside an expression) has no place in the
+//canonical style, so that statement is written as it was in the source,
+//only moved to the indentation of the block it is in
+pub struct Formatter<'a> {
+    source: &'a str,
+    comments: &'a [Comment],
+    //Index of the next comment to be written
+    next_comment: usize,
+    //Byte offset of the source that has already been written
+    written: usize,
+    //Nothing has been written yet in the current block. It never starts with a blank line
+    block_start: bool,
+    indent: usize,
+    out: String,
+}
+
+impl<'a> Formatter<'a> {
+    pub fn new(source: &'a str, comments: &'a [Comment]) -> Self {
+        Self {
+            source,
+            comments,
+            next_comment: 0,
+            written: 0,
+            block_start: true,
+            indent: 0,
+            out: String::new(),
+        }
+    }
+
+    pub fn format(mut self, stmts: &[Stmt]) -> String {
+        self.stmt_list(stmts.iter(), self.source.len());
+        self.out
+    }
+
+    //The statements of a block (or of the whole program) one per line.
+    //The comments before the end of the block are written inside it
+    fn stmt_list<'s>(&mut self, stmts: impl Iterator<Item = &'s Stmt>, end: usize) {
+        for stmt in stmts {
+            self.item_start(stmt.span.start);
+            match self.has_inner_comments(stmt) {
+                true => self.verbatim(stmt),
+                false => self.stmt(stmt),
+            }
+            self.item_end(stmt.span.end);
+        }
+        self.comments_before(end);
+    }
+
+    //Some comment is inside the statement, but not inside one of the
+    //blocks of it, where it would be written between the inner statements
+    fn has_inner_comments(&self, stmt: &Stmt) -> bool {
+        let mut blocks = Vec::new();
+        self.inner_blocks(stmt, &mut blocks);
+        self.comments[self.next_comment..]
+            .iter()
+            .take_while(|c| c.span.start < stmt.span.end)
+            .any(|c| {
+                c.span.start >= stmt.span.start
+                    && !blocks
+                        .iter()
+                        .any(|(from, to)| c.span.start >= *from && c.span.end <= *to)
+            })
+    }
+
+    //Ranges of the source whose comments are written by a nested stmt_list
+    fn inner_blocks(&self, stmt: &Stmt, blocks: &mut Vec<(usize, usize)>) {
+        if let Some(parts) = desugared_for(stmt) {
+            return self.inner_blocks(parts.body, blocks);
+        }
+        match &stmt.kind {
+            StmtKind::BLOCK(_) => blocks.push((stmt.span.start + 1, stmt.span.end - 1)),
+            StmtKind::IF(_, then, else_) => {
+                self.inner_blocks(then, blocks);
+                if let Some(else_) = else_ {
+                    self.inner_blocks(else_, blocks);
+                }
+            }
+            StmtKind::WHILE(_, body) => self.inner_blocks(body, blocks),
+            StmtKind::FUNCTION(decl) => {
+                let open = self.find(decl.name.span.end, '{');
+                blocks.push((open + 1, stmt.span.end - 1));
+            }
+            //The methods and the space between them
+            StmtKind::CLASS(name, _, methods) => {
+                let mut from = self.find(name.span.end, '{') + 1;
+                for method in methods.iter() {
+                    let close = self.function_end(method);
+                    blocks.push((from, method.name.span.start));
+                    blocks.push((self.find(method.name.span.end, '{') + 1, close));
+                    from = close + 1;
+                }
+                blocks.push((from, stmt.span.end - 1));
+            }
+            _ => {}
+        }
+    }
+
+    //The statement as it is in the source, with the comments in it. The
+    //lines after the first one keep their indentation relative to it,
+    //except the ones that start inside a string
+    fn verbatim(&mut self, stmt: &Stmt) {
+        let source = self.source;
+        let (start, end) = (stmt.span.start, stmt.span.end);
+        let line_start = source[..start].rfind('\n').map_or(0, |i| i + 1);
+        let base = source[line_start..start]
+            .chars()
+            .take_while(|c| *c == ' ' || *c == '\t')
+            .count();
+
+        let mut in_string = false;
+        let mut offset = start;
+        for (i, line) in source[start..end].split('\n').enumerate() {
+            if i > 0 {
+                self.out += "\n";
+            }
+            if i == 0 || in_string {
+                self.out += line;
+            } else {
+                let spaces = line.len() - line.trim_start_matches([' ', '\t']).len();
+                let rest = &line[spaces.min(base)..];
+                if !rest.trim().is_empty() {
+                    self.push_indent();
+                    self.out += rest;
+                }
+            }
+            for (j, c) in line.char_indices() {
+                if c == '"' && !self.in_comment(offset + j) {
+                    in_string = !in_string;
+                }
+            }
+            offset += line.len() + 1;
+        }
+
+        while self
+            .comments
+            .get(self.next_comment)
+            .is_some_and(|c| c.span.start < end)
+        {
+            self.next_comment += 1;
+        }
+        self.written = end;
+    }
+
+    fn in_comment(&self, offset: usize) -> bool {
+        self.comments[self.next_comment..]
+            .iter()
+            .any(|c| c.span.start <= offset && offset < c.span.end)
+    }
+
+    //Writes the comments before the item, a blank line if the source had
+    //one and the indentation
+    fn item_start(&mut self, start: usize) {
+        self.comments_before(start);
+        self.blank_line_before(start);
+        self.push_indent();
+    }
+
+    fn item_end(&mut self, end: usize) {
+        self.written = self.written.max(end);
+        self.trailing_comments();
+        self.out += "\n";
+        self.block_start = false;
+    }
+
+    //The comments in the same line as what has been written stay in that line
+    fn trailing_comments(&mut self) {
+        while let Some(comment) = self.comments.get(self.next_comment) {
+            let between = self.source.get(self.written..comment.span.start);
+            if !between.is_some_and(|b| !b.contains('\n') && b.trim().is_empty()) {
+                break;
+            }
+            self.out += " ";
+            self.out += &comment.text;
+            self.written = comment.span.end;
+            self.next_comment += 1;
+        }
+    }
+
+    fn comments_before(&mut self, offset: usize) {
+        while let Some(comment) = self.comments.get(self.next_comment) {
+            if comment.span.start >= offset {
+                break;
+            }
+            self.blank_line_before(comment.span.start);
+            self.push_indent();
+            self.out += &comment.text;
+            self.out += "\n";
+            self.written = comment.span.end;
+            self.block_start = false;
+            self.next_comment += 1;
+        }
+    }
+
+    fn blank_line_before(&mut self, offset: usize) {
+        let between = self.source.get(self.written..offset).unwrap_or("");
+        if !self.block_start && between.matches('\n').count() > 1 {
+            self.out += "\n";
+        }
+    }
+
+    fn push_indent(&mut self) {
+        self.out += &INDENT.repeat(self.indent);
+    }
+
+    //First time the char appears from the offset, skipping the comments.
+    //Used to find the braces of the nodes that dont keep their spans
+    fn find(&self, from: usize, c: char) -> usize {
+        let mut comments = self
+            .comments
+            .iter()
+            .filter(|co| co.span.end > from)
+            .peekable();
+        let mut offset = from;
+        for ch in self.source.get(from..).unwrap_or("").chars() {
+            while comments.peek().is_some_and(|co| co.span.end <= offset) {
+                comments.next();
+            }
+            let in_comment = comments.peek().is_some_and(|co| co.span.start <= offset);
+            if ch == c && !in_comment {
+                return offset;
+            }
+            offset += ch.len_utf8();
+        }
+        self.source.len()
+    }
+
+    //{ and } are at the received offsets. The content is written indented
+    fn block<'s>(&mut self, stmts: impl Iterator<Item = &'s Stmt>, open: usize, close: usize) {
+        let mut stmts = stmts.peekable();
+        let has_comments = self
+            .comments
+            .get(self.next_comment)
+            .is_some_and(|c| c.span.start < close);
+        if stmts.peek().is_none() && !has_comments {
+            self.out += "{}";
+            self.written = close + 1;
+            return;
+        }
+
+        self.out += "{";
+        self.written = open + 1;
+        self.trailing_comments();
+        self.out += "\n";
+        self.block_start = true;
+        self.indent += 1;
+        self.stmt_list(stmts, close);
+        self.indent -= 1;
+        self.push_indent();
+        self.out += "}";
+        self.written = close + 1;
+    }
+
+    //The bodies of if, while and for. Blocks stay in the same line
+    fn body(&mut self, stmt: &Stmt) {
+        match &stmt.kind {
+            StmtKind::BLOCK(stmts) if desugared_for(stmt).is_none() => {
+                self.out += " ";
+                self.block(
+                    stmts.iter().map(|s| s.as_ref()),
+                    stmt.span.start,
+                    stmt.span.end - 1,
+                );
+            }
+            _ => {
+                self.out += "\n";
+                self.indent += 1;
+                self.push_indent();
+                self.stmt(stmt);
+                self.indent -= 1;
+            }
+        }
+    }
+
+    fn stmt(&mut self, stmt: &Stmt) {
+        if let Some(parts) = desugared_for(stmt) {
+            return self.for_stmt(parts);
+        }
+
+        match &stmt.kind {
+            StmtKind::PRINT(e) => self.out += &format!("print {};", self.expr(e)),
+            StmtKind::EXPR(e) => self.out += &format!("{};", self.expr(e)),
+            StmtKind::VAR(name, type_, initializer) => {
+                let name = typed_name(name, type_.as_deref());
+                match initializer {
+                    Some(value) => self.out += &format!("var {} = {};", name, self.expr(value)),
+                    None => self.out += &format!("var {};", name),
+                }
+            }
+            StmtKind::CONST(name, type_, value) => {
+                let name = typed_name(name, type_.as_deref());
+                self.out += &format!("const {} = {};", name, self.expr(value))
+            }
+            StmtKind::BLOCK(stmts) => self.block(
+                stmts.iter().map(|s| s.as_ref()),
+                stmt.span.start,
+                stmt.span.end - 1,
+            ),
+            StmtKind::IF(condition, then, else_) => {
+                self.out += &format!("if ({})", self.expr(condition));
+                self.body(then);
+                if let Some(else_) = else_ {
+                    match then.kind {
+                        StmtKind::BLOCK(_) => self.out += " ",
+                        _ => {
+                            self.out += "\n";
+                            self.push_indent();
+                        }
+                    }
+                    self.out += "else";
+                    //else if chains stay in the same line
+                    match else_.kind {
+                        StmtKind::IF(_, _, _) => {
+                            self.out += " ";
+                            self.stmt(else_);
+                        }
+                        _ => self.body(else_),
+                    }
+                }
+            }
+            StmtKind::WHILE(condition, body) => {
+                self.out += &format!("while ({})", self.expr(condition));
+                self.body(body);
+            }
+            StmtKind::FUNCTION(decl) => {
+                self.out += "fun ";
+                self.function(decl, stmt.span.end - 1);
+            }
+            StmtKind::RETURN(_, value) => match value {
+                Some(value) => self.out += &format!("return {};", self.expr(value)),
+                None => self.out += "return;",
+            },
+            StmtKind::CLASS(name, superclass, methods) => {
+                self.out += &format!("class {}", name.lexeme);
+                if let Some(superclass) = superclass {
+                    self.out += &format!(" < {}", self.expr(superclass));
+                }
+                self.out += " ";
+                self.class_body(name.span.end, stmt.span.end - 1, methods);
+            }
+        }
+    }
+
+    fn class_body(&mut self, from: usize, close: usize, methods: &[std::rc::Rc<FunctionDecl>]) {
+        let open = self.find(from, '{');
+        if methods.is_empty() {
+            return self.block(std::iter::empty(), open, close);
+        }
+
+        self.out += "{";
+        self.written = open + 1;
+        self.trailing_comments();
+        self.out += "\n";
+        self.block_start = true;
+        self.indent += 1;
+        for method in methods {
+            self.item_start(method.name.span.start);
+            let end = self.function_end(method);
+            self.function(method, end);
+            self.item_end(end + 1);
+        }
+        self.comments_before(close);
+        self.indent -= 1;
+        self.push_indent();
+        self.out += "}";
+        self.written = close + 1;
+    }
+
+    //The } of a method. Only the whole class keeps its span
+    fn function_end(&self, decl: &FunctionDecl) -> usize {
+        let from = match decl.body.last() {
+            Some(last) => last.span.end,
+            None => self.find(decl.name.span.end, '{') + 1,
+        };
+        self.find(from, '}')
+    }
+
+    //name(params) { body }
+    fn function(&mut self, decl: &FunctionDecl, close: usize) {
+        self.out += &format!("{}({})", decl.name.lexeme, decl.typed_params().join(", "));
+        if let Some(return_type) = &decl.return_type {
+            self.out += &format!(": {}", return_type.lexeme);
+        }
+        self.out += " ";
+        let open = self.find(decl.name.span.end, '{');
+        self.block(decl.body.iter().map(|s| s.as_ref()), open, close);
+    }
+
+    fn for_stmt(&mut self, parts: ForParts) {
+        self.out += "for (";
+        match parts.initializer {
+            Some(initializer) => self.stmt(initializer),
+            None => self.out += ";",
+        }
+        if let Some(condition) = parts.condition {
+            self.out += &format!(" {}", self.expr(condition));
+        }
+        self.out += ";";
+        if let Some(increment) = parts.increment {
+            self.out += &format!(" {}", self.expr(increment));
+        }
+        self.out += ")";
+        self.body(parts.body);
+    }
+
+    fn expr(&self, e: &Expr) -> String {
+        match &e.kind {
+            ExprKind::Binary(left, operator, right) | ExprKind::Logical(left, operator, right) => {
+                format!(
+                    "{} {} {}",
+                    self.expr(left),
+                    operator.lexeme,
+                    self.expr(right)
+                )
+            }
+            ExprKind::Grouping(e) => format!("({})", self.expr(e)),
+            ExprKind::Conditional(condition, then, else_) => {
+                format!(
+                    "{} ? {} : {}",
+                    self.expr(condition),
+                    self.expr(then),
+                    self.expr(else_)
+                )
+            }
+            ExprKind::NumberLit(n) => n.to_string(),
+            //Written as it is in the source, the value has lost its line breaks
+            ExprKind::StringLit(_) => self
+                .source
+                .get(e.span.start..e.span.end)
+                .unwrap_or("")
+                .to_string(),
+            ExprKind::Unary(operator, right) => format!("{}{}", operator.lexeme, self.expr(right)),
+            ExprKind::Variable(name, _) => name.to_string(),
+            ExprKind::Assignment(name, value, _) => format!("{} = {}", name, self.expr(value)),
+            ExprKind::CompoundAssignment(name, operator, value, _) => {
+                format!("{} {} {}", name, operator.lexeme, self.expr(value))
+            }
+            ExprKind::Boolean(b) => b.to_string(),
+            ExprKind::Call(callee, _, arguments) => {
+                let arguments: Vec<String> = arguments.iter().map(|a| self.expr(a)).collect();
+                format!("{}({})", self.expr(callee), arguments.join(", "))
+            }
+            ExprKind::Get(object, name) => format!("{}.{}", self.expr(object), name.lexeme),
+            ExprKind::Set(object, name, value) => {
+                format!(
+                    "{}.{} = {}",
+                    self.expr(object),
+                    name.lexeme,
+                    self.expr(value)
+                )
+            }
+            ExprKind::CompoundSet(object, name, operator, value) => format!(
+                "{}.{} {} {}",
+                self.expr(object),
+                name.lexeme,
+                operator.lexeme,
+                self.expr(value)
+            ),
+            ExprKind::This(_, _) => String::from("this"),
+            ExprKind::Super(_, method, _) => format!("super.{}", method.lexeme),
+            ExprKind::Nil => String::from("nil"),
+        }
+    }
+}
+
+//The parts of a for loop, taken back from the nodes it was desugared into
+struct ForParts<'s> {
+    initializer: Option<&'s Stmt>,
+    condition: Option<&'s Expr>,
+    increment: Option<&'s Expr>,
+    body: &'s Stmt,
+}
+
+//The parser turns for (init; cond; incr) body into
+//  BLOCK([init, WHILE(cond, BLOCK([body, incr]))])
+//where the new nodes take the span of the whole for. A block written by
+//hand can not have the same span as a statement inside it (the braces
+//are part of the block), so the span tells them apart
+fn desugared_for(stmt: &Stmt) -> Option<ForParts<'_>> {
+    let StmtKind::BLOCK(stmts) = &stmt.kind else {
+        return None;
+    };
+    let while_stmt = stmts.last()?;
+    let StmtKind::WHILE(condition, while_body) = &while_stmt.kind else {
+        return None;
+    };
+    let StmtKind::BLOCK(body) = &while_body.kind else {
+        return None;
+    };
+    if while_stmt.span != stmt.span || while_body.span != stmt.span || stmts.len() > 2 {
+        return None;
+    }
+
+    let increment = match body.get(1).map(|s| &s.kind) {
+        Some(StmtKind::EXPR(e)) => Some(e.as_ref()),
+        _ => None,
+    };
+    Some(ForParts {
+        initializer: stmts.len().eq(&2).then(|| stmts[0].as_ref()),
+        //A missing condition is a true with the span of the whole for
+        condition: (condition.span != stmt.span).then_some(condition.as_ref()),
+        increment,
+        body: body.first()?,
+    })
+}
+
+#[cfg(test)]
+mod formatter_tests {
+    use crate::lox::Lox;
+
+    fn format(source: &str) -> String {
+        Lox::new().format(source).unwrap()
+    }
+
+    #[test]
+    fn spacing_and_indentation() {
+        assert_eq!(
+            format("var a=1;{print a+2*(3-a);if(a<2){a=-a;}else{print!true;}}"),
+            "var a = 1;\n\
+             {\n  \
+               print a + 2 * (3 - a);\n  \
+               if (a < 2) {\n    \
+                 a = -a;\n  \
+               } else {\n    \
+                 print !true;\n  \
+               }\n\
+             }\n"
+        );
+        assert_eq!(
+            format("class A<B{init(x){this.x=x;}m(){return super.m(1,2);}} fun f(){}"),
+            "class A < B {\n  \
+               init(x) {\n    \
+                 this.x = x;\n  \
+               }\n  \
+               m() {\n    \
+                 return super.m(1, 2);\n  \
+               }\n\
+             }\n\
+             fun f() {}\n"
+        );
+    }
+
+    #[test]
+    fn for_loops_are_written_back() {
+        assert_eq!(
+            format("for(var i=0;i<3;i=i+1){print i;} for(;;){print 1;}"),
+            "for (var i = 0; i < 3; i = i + 1) {\n  print i;\n}\nfor (;;) {\n  print 1;\n}\n"
+        );
+    }
+
+    #[test]
+    fn bodies_without_braces() {
+        assert_eq!(
+            format("if(a)print 1;else if(b){print 2;}else print 3;while(a)a-=1;"),
+            "if (a)\n  print 1;\nelse if (b) {\n  print 2;\n} else\n  print 3;\nwhile (a)\n  a -= 1;\n"
+        );
+    }
+
+    #[test]
+    fn compound_assignments() {
+        assert_eq!(format("a+=1;a.b*=a-=2;"), "a += 1;\na.b *= a -= 2;\n");
+    }
+
+    #[test]
+    fn multi_line_strings_keep_their_lines() {
+        assert_eq!(
+            format("print  \"a\nb\";var s=\"\n\";"),
+            "print \"a\nb\";\nvar s = \"\n\";\n"
+        );
+    }
+
+    #[test]
+    fn conditionals() {
+        assert_eq!(format("print a?b:(c?d:e);"), "print a ? b : (c ? d : e);\n");
+    }
+
+    #[test]
+    fn type_annotations() {
+        assert_eq!(
+            format("var a:number=1;fun f(a:string,b):nil{}"),
+            "var a: number = 1;\nfun f(a: string, b): nil {}\n"
+        );
+    }
+
+    #[test]
+    fn comments_inside_a_statement_stay_there() {
+        assert_eq!(
+            format("print 1 + /* inline */ 2;\nprint  3;"),
+            "print 1 + /* inline */ 2;\nprint 3;\n"
+        );
+        //Only moved to the indentation of the block
+        let source = "{\n    if (a) // why\n    {\n      print 1;\n    }\n}\n";
+        let formatted = format(source);
+        assert_eq!(formatted, "{\n  if (a) // why\n  {\n    print 1;\n  }\n}\n");
+        assert_eq!(format(&formatted), formatted);
+        //But the lines of a string are part of its value
+        assert_eq!(
+            format("{\n    print /* a */ \"x\n    y\";\n}"),
+            "{\n  print /* a */ \"x\n    y\";\n}\n"
+        );
+        //The comments of the inner blocks don't stop the formatting
+        assert_eq!(
+            format("if(a){ // why\nprint 1;}"),
+            "if (a) { // why\n  print 1;\n}\n"
+        );
+    }
+
+    #[test]
+    fn comments_are_kept() {
+        let source = "// header\nvar a = 1; // trailing\n\n\n\n/* before */ print a; /* a */ // b\n{\n  // only one\n}\n// end";
+        assert_eq!(
+            format(source),
+            "// header\n\
+             var a = 1; // trailing\n\
+             \n\
+             /* before */\n\
+             print a; /* a */ // b\n\
+             {\n  \
+               // only one\n\
+             }\n\
+             // end\n"
+        );
+    }
+
+    #[test]
+    fn formatting_is_idempotent() {
+        let source =
+            "fun f(a,b){ // sum\n\n  return a+b;}\n\n\nclass A{} print f(1,2)==3 and nil or \"s\";";
+        let formatted = format(source);
+        assert_eq!(format(&formatted), formatted);
+    }
+
+    #[test]
+    fn invalid_sources_are_not_formatted() {
+        assert!(Lox::new().format("print 1 +;").is_err());
+    }
+}
@@ -1,14 +1,25 @@
-use std::fmt::Debug;
+use std::{
+    cell::RefCell,
+    collections::HashMap,
+    fmt::{Debug, Display},
+    io::{self, BufRead, BufReader, Write},
+    rc::Rc,
+};
 
 use crate::{
     ast::{
-        expr::Expr,
-        stmt::{self, Stmt},
+        expr::{Depth, Expr, ExprKind},
+        span::Span,
+        stmt::{FunctionDecl, Stmt, StmtKind},
         token::Token,
         token::TokenType,
     },
+    callable::{Callable, Function, THIS},
+    class::{Class, INITIALIZER, SUPER},
     enviroment::Environment,
-    object::Object,
+    native::NativeFunction,
+    object::{Object, Semantics},
+    stack::{StackGuard, STACK_RESERVE},
 };
 #[derive(Debug, PartialEq)]
 pub enum RuntimeError {
@@ -16,66 +27,373 @@ pub enum RuntimeError {
     UnknownError,
     UnknownExpression(String),
     UndefinedVariable(String),
-    ScopeError(Option<String>),
+    UndefinedProperty(String),
+    ConstAssignment(String),
+    ArityError(String),
+    IoError(String),
+    StackOverflow,
+    //Not an actual error. A return statement unwinds through every nested
+    //stmt until the function call that is waiting for the value
+    Return(Object),
+}
+
+impl RuntimeError {
+    //The description alone, without the error kind prefix
+    pub fn message(&self) -> String {
+        match self {
+            Self::TypeError(e) => e.clone(),
+            Self::UnknownError => String::from("Unknown error"),
+            Self::UnknownExpression(e) => e.clone(),
+            Self::UndefinedVariable(name) => format!("Undefined variable {}", name),
+            Self::UndefinedProperty(name) => format!("Undefined property {}", name),
+            Self::ConstAssignment(name) => format!("Can't assign to the constant {}", name),
+            Self::ArityError(e) => e.clone(),
+            Self::Return(_) => String::from("Return outside of a function"),
+            Self::IoError(e) => e.clone(),
+            Self::StackOverflow => String::from("Stack overflow, too many nested calls"),
+        }
+    }
+}
+
+impl Display for RuntimeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "[Error] - Runtime error: {}", self.message())
+    }
+}
+
+//A call being executed. The call site is the call expression in the caller
+#[derive(Debug, PartialEq, Clone)]
+pub struct Frame {
+    pub function: String,
+    pub call_site: Span,
 }
 
-pub struct Interpreter<'a> {
-    enviroment: &'a mut Environment,
+//The error that stopped the program, with the place where it happened.
+//The span is the innermost node that failed, and the stack has the calls
+//that were active at that moment (the most recent last)
+#[derive(Debug, PartialEq)]
+pub struct RuntimeFailure {
+    pub error: RuntimeError,
+    pub span: Option<Span>,
+    pub stack: Vec<Frame>,
 }
 
-impl<'a> Interpreter<'a> {
-    pub fn new(enviroment: &'a mut Environment) -> Self {
-        Self { enviroment }
+impl RuntimeFailure {
+    //Lines of the traceback, from the most recent call to the script.
+    //Each one tells where the execution was inside that function.
+    //The same line repeated by a recursion is shown once, with a count
+    pub fn traceback(&self) -> Vec<String> {
+        let mut lines: Vec<(String, usize)> = Vec::new();
+        let mut line = self.span.map(|s| s.line);
+        for frame in self.stack.iter().rev() {
+            let current = trace_line(line, &frame.function);
+            match lines.last_mut() {
+                Some((last, times)) if *last == current => *times += 1,
+                _ => lines.push((current, 1)),
+            }
+            line = Some(frame.call_site.line);
+        }
+        lines.push((trace_line(line, SCRIPT), 1));
+        lines
+            .into_iter()
+            .map(|(line, times)| match times {
+                1 => line,
+                _ => format!("{} ({} times)", line, times),
+            })
+            .collect()
+    }
+}
+
+//Name of the top level code in the traceback
+const SCRIPT: &str = "<script>";
+
+fn trace_line(line: Option<usize>, function: &str) -> String {
+    match line {
+        Some(line) => format!("line {}, in {}", line, function),
+        None => format!("in {}", function),
+    }
+}
+
+impl Display for RuntimeFailure {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.error)?;
+        for line in self.traceback() {
+            write!(f, "\n    {}", line)?;
+        }
+        Ok(())
+    }
+}
+
+//The interpreter works over a handle to the scope that is being executed.
+//The caller keeps its own handle to the received environment, so whatever
+//is defined at global level is still there after the interpretation
+//The streams are shared, so the caller can keep a handle to them and
+//read (ie. in tests) what the program has printed
+pub type Output = Rc<RefCell<dyn Write>>;
+pub type Input = Rc<RefCell<dyn BufRead>>;
+
+pub struct Interpreter {
+    globals: Environment,
+    enviroment: Environment,
+    output: Output,
+    input: Input,
+    //Calls being executed, the most recent last
+    frames: Vec<Frame>,
+    //Every call and nested expression recurses in the host stack
+    stack: StackGuard,
+    //Where the current error was raised. Filled by the innermost failing node
+    failure: Option<(Span, Vec<Frame>)>,
+    semantics: Semantics,
+}
+
+impl Interpreter {
+    //Prints to the standard output and reads from the standard input
+    pub fn new(enviroment: &Environment) -> Self {
+        Self::with_streams(
+            enviroment,
+            Rc::new(RefCell::new(io::stdout())),
+            Rc::new(RefCell::new(BufReader::new(io::stdin()))),
+        )
+    }
+
+    pub fn with_streams(enviroment: &Environment, output: Output, input: Input) -> Self {
+        Self {
+            globals: enviroment.clone(),
+            enviroment: enviroment.clone(),
+            output,
+            input,
+            frames: Vec::new(),
+            stack: StackGuard::default(),
+            failure: None,
+            semantics: Semantics::default(),
+        }
+    }
+
+    pub fn set_semantics(&mut self, semantics: Semantics) {
+        self.semantics = semantics;
+    }
+
+    //Size of the stack of the thread that runs the scripts
+    pub fn set_stack_size(&mut self, stack_size: usize) {
+        self.stack = StackGuard::new(stack_size);
+    }
+
+    //Reads a line from the input stream, without the line break.
+    //None means that the stream has ended
+    pub fn read_line(&mut self) -> Result<Option<String>, RuntimeError> {
+        let mut line = String::new();
+        let read = self
+            .input
+            .borrow_mut()
+            .read_line(&mut line)
+            .map_err(|e| RuntimeError::IoError(format!("Could not read the input: {}", e)))?;
+        if read == 0 {
+            return Ok(None);
+        }
+        Ok(Some(line.trim_end_matches(['\n', '\r']).to_string()))
+    }
+
+    fn write_line(&mut self, value: &Object) -> ExcecuteStmtRes {
+        let printed = match (value, self.semantics) {
+            //Before following the book, nil was printed as Nil
+            (Object::NullObj, Semantics::Permissive) => String::from("Nil"),
+            _ => value.to_string(),
+        };
+        writeln!(self.output.borrow_mut(), "{}", printed)
+            .map_err(|e| RuntimeError::IoError(format!("Could not write the output: {}", e)))
+    }
+
+    //Exposes a Rust closure to the scripts as a global function
+    pub fn define_native<F>(
+        &mut self,
+        name: &str,
+        arity: usize,
+        function: F,
+    ) -> Result<(), RuntimeError>
+    where
+        F: Fn(&mut Interpreter, Vec<Object>) -> Result<Object, RuntimeError> + 'static,
+    {
+        NativeFunction::new(name, arity, function).define_in(&mut self.globals)
     }
 }
 
 type EvalRes = Result<Object, RuntimeError>;
 type ExcecuteStmtRes = Result<(), RuntimeError>;
 
-impl<'a> Interpreter<'a> {
-    pub fn interpret(&mut self, stmts: &'a Vec<Stmt>) {
+impl Interpreter {
+    //Stops on the first error. If the last statement is an expression
+    //statement its value is returned, otherwise the result is nil
+    pub fn interpret(&mut self, stmts: &[Stmt]) -> Result<Object, RuntimeFailure> {
+        self.stack.start();
+        let mut last = Object::NullObj;
         for s in stmts.iter() {
-            if let Err(e) = self.execute_stmt(s) {
-                println!("{:?}", e);
-                break;
+            last = match &s.kind {
+                StmtKind::EXPR(e) => self.evaluate_expr(e).map_err(|e| self.fail(e))?,
+                _ => {
+                    self.execute_stmt(s).map_err(|e| self.fail(e))?;
+                    Object::NullObj
+                }
             };
         }
+        Ok(last)
     }
 
-    fn execute_stmt(&mut self, stmt: &'a Stmt) -> ExcecuteStmtRes {
-        match stmt {
+    //Used by the prompt. Works as interpret, but the value of
+    //every expression statement is printed back to the user
+    pub fn interpret_echo(&mut self, stmts: &[Stmt]) -> Result<(), RuntimeFailure> {
+        self.stack.start();
+        for s in stmts.iter() {
+            let res = match &s.kind {
+                StmtKind::EXPR(e) => self
+                    .evaluate_expr(e)
+                    .and_then(|value| self.write_line(&value)),
+                _ => self.execute_stmt(s),
+            };
+            res.map_err(|e| self.fail(e))?;
+        }
+        Ok(())
+    }
+
+    //Attaches the location of the error. The call stack is emptied, the
+    //interpreter can be used again after a failure
+    fn fail(&mut self, error: RuntimeError) -> RuntimeFailure {
+        let (span, stack) = match self.failure.take() {
+            Some((span, stack)) => (Some(span), stack),
+            None => (None, Vec::new()),
+        };
+        self.frames.clear();
+        RuntimeFailure { error, span, stack }
+    }
+
+    //Only the first node that sees the error keeps its location, the
+    //outer ones just propagate it. A return is not an error at all
+    fn locate(&mut self, error: &RuntimeError, span: Span) {
+        if self.failure.is_none() && !matches!(error, RuntimeError::Return(_)) {
+            self.failure = Some((span, self.frames.clone()));
+        }
+    }
+
+    fn execute_stmt(&mut self, stmt: &Stmt) -> ExcecuteStmtRes {
+        let res = self.execute_stmt_kind(stmt);
+        if let Err(e) = &res {
+            self.locate(e, stmt.span);
+        }
+        res
+    }
+
+    fn execute_stmt_kind(&mut self, stmt: &Stmt) -> ExcecuteStmtRes {
+        match &stmt.kind {
             //Todo: Ingore value?
-            Stmt::EXPR(e) => match self.evaluate_expr(e) {
+            StmtKind::EXPR(e) => match self.evaluate_expr(e) {
                 Err(e) => Err(e),
                 Ok(_) => Ok(()),
             },
-            Stmt::PRINT(e) => match self.evaluate_expr(e) {
-                Ok(value) => {
-                    println!("{}", value);
-                    return Ok(());
-                }
-                Err(e) => {
-                    panic!("{:?}", e);
-                }
-            },
-            Stmt::VAR(name, declaration) => {
+            StmtKind::PRINT(e) => {
+                let value = self.evaluate_expr(e)?;
+                self.write_line(&value)
+            }
+            StmtKind::VAR(name, _, declaration) => {
                 self.evaluate_declaration(name, declaration)?;
                 return Ok(());
             }
-            Stmt::BLOCK(stmts) => {
-                _ = self.excecute_block(stmts);
-                return Ok(());
+            StmtKind::CONST(name, _, value) => {
+                let value = self.evaluate_expr(value)?;
+                self.enviroment.define_const(&name.lexeme, value)
             }
-            Stmt::IF(condition, then, else_) => {
+            StmtKind::BLOCK(stmts) => self.excecute_block(stmts),
+            StmtKind::IF(condition, then, else_) => {
                 self.excecute_if(condition, then, else_)?;
                 Ok(())
             }
-            Stmt::WHILE(condition, body) => self.excecute_while(condition, body), // _ => todo!(),
+            StmtKind::WHILE(condition, body) => self.excecute_while(condition, body), // _ => todo!(),
+            StmtKind::FUNCTION(declaration) => {
+                //The function captures the scope where it is declared
+                let function = Object::FunctionObj(Function::new(
+                    declaration.clone(),
+                    self.enviroment.clone(),
+                    false,
+                ));
+                self.enviroment
+                    .define(&declaration.name.lexeme, Some(function))
+            }
+            StmtKind::CLASS(name, superclass, methods) => {
+                self.excecute_class(name, superclass, methods)
+            }
+            StmtKind::RETURN(_, value) => {
+                let value = match value {
+                    Some(v) => self.evaluate_expr(v)?,
+                    None => Object::NullObj,
+                };
+                Err(RuntimeError::Return(value))
+            }
+        }
+    }
+
+    fn excecute_class(
+        &mut self,
+        name: &Token,
+        superclass: &Option<Box<Expr>>,
+        methods: &Vec<Rc<FunctionDecl>>,
+    ) -> ExcecuteStmtRes {
+        let superclass = match superclass {
+            None => None,
+            Some(expr) => match self.evaluate_expr(expr)? {
+                Object::ClassObj(c) => Some(c),
+                o => {
+                    return Err(RuntimeError::TypeError(format!(
+                        "Superclass of {} must be a class, got {:?}",
+                        name.lexeme, o
+                    )))
+                }
+            },
+        };
+
+        //With a superclass, the methods close over an extra scope holding `super`.
+        //The resolver counts that scope between the class and `this`
+        let mut methods_scope = self.enviroment.clone();
+        if let Some(s) = &superclass {
+            methods_scope = self.enviroment.new_enclosed();
+            methods_scope.define(&SUPER.to_string(), Some(Object::ClassObj(s.clone())))?;
         }
+
+        let mut class_methods = HashMap::new();
+        for method in methods {
+            let function = Function::new(
+                method.clone(),
+                methods_scope.clone(),
+                method.name.lexeme == INITIALIZER,
+            );
+            class_methods.insert(method.name.lexeme.clone(), function);
+        }
+
+        let class = Class::new(name.lexeme.clone(), superclass, class_methods);
+        self.enviroment
+            .define(&name.lexeme, Some(Object::ClassObj(Rc::new(class))))
     }
 
-    fn excecute_while(&mut self, condition: &'a Box<Expr>, body: &'a Box<Stmt>) -> ExcecuteStmtRes {
-        while self.evaluate_expr(&condition)?.is_truthy() {
+    //Runs the body of a function inside a new scope, child of the scope
+    //captured when the function was declared (closure), where the
+    //parameters are bound to the received arguments
+    pub fn execute_function(
+        &mut self,
+        declaration: &FunctionDecl,
+        closure: &Environment,
+        arguments: Vec<Object>,
+    ) -> EvalRes {
+        let mut scope = closure.new_enclosed();
+        for (param, value) in declaration.params.iter().zip(arguments) {
+            scope.define(&param.lexeme, Some(value))?;
+        }
+
+        match self.excecute_block_in(&declaration.body, scope) {
+            Ok(()) => Ok(Object::NullObj),
+            Err(RuntimeError::Return(value)) => Ok(value),
+            Err(err) => Err(err),
+        }
+    }
+
+    fn excecute_while(&mut self, condition: &Box<Expr>, body: &Box<Stmt>) -> ExcecuteStmtRes {
+        while self.evaluate_expr(&condition)?.is_truthy(self.semantics)? {
             self.execute_stmt(&body)?;
         }
 
@@ -84,12 +402,12 @@ impl<'a> Interpreter<'a> {
 
     fn excecute_if(
         &mut self,
-        condition: &'a Box<Expr>,
-        then: &'a Box<Stmt>, //This is a block
-        else_: &'a Option<Box<Stmt>>,
+        condition: &Box<Expr>,
+        then: &Box<Stmt>, //This is a block
+        else_: &Option<Box<Stmt>>,
     ) -> ExcecuteStmtRes {
         let condition_value = self.evaluate_expr(&condition)?;
-        if condition_value.is_truthy() {
+        if condition_value.is_truthy(self.semantics)? {
             self.execute_stmt(&then)?;
         } else if let Some(else_block) = else_ {
             self.execute_stmt(&else_block)?;
@@ -97,27 +415,35 @@ impl<'a> Interpreter<'a> {
         Ok(())
     }
 
-    fn excecute_block(&mut self, stmts: &'a Vec<Box<Stmt>>) -> ExcecuteStmtRes {
+    fn excecute_block(&mut self, stmts: &Vec<Box<Stmt>>) -> ExcecuteStmtRes {
         //Initialize the new local scope for the block.
         //Any new variable will be added to the current scope,
         //But assignations and gets will try in the local,
         //and,on fail, will try one level above (Until global)
-        self.enviroment.add_new_local()?;
+        let scope = self.enviroment.new_enclosed();
+        self.excecute_block_in(stmts, scope)
+    }
+
+    //Executes the stmts using the received scope as the current one.
+    //The previous scope is restored after the execution, even on error
+    fn excecute_block_in(&mut self, stmts: &Vec<Box<Stmt>>, scope: Environment) -> ExcecuteStmtRes {
+        let previous = std::mem::replace(&mut self.enviroment, scope);
+        let mut result = Ok(());
         for stmt in stmts {
-            if let Err(err) = self.execute_stmt(stmt) {
-                self.enviroment.remove_local()?;
-                return Err(err);
+            result = self.execute_stmt(stmt);
+            if result.is_err() {
+                break;
             }
         }
 
-        self.enviroment.remove_local()?;
-        Ok(())
+        self.enviroment = previous;
+        result
     }
 
     fn evaluate_declaration(
         &mut self,
-        name: &'a Box<Token>,
-        declaration: &'a Option<Box<Expr>>,
+        name: &Box<Token>,
+        declaration: &Option<Box<Expr>>,
     ) -> ExcecuteStmtRes {
         let mut val: Option<Object> = None;
         if let Some(e) = declaration {
@@ -128,38 +454,184 @@ impl<'a> Interpreter<'a> {
         self.enviroment.define(&name.lexeme.clone(), val)?;
         Ok(())
     }
-    fn evaluate_expr(&mut self, expr: &'a Expr) -> EvalRes {
-        match expr {
-            Expr::NumberLit(n) => return Ok(Object::NumberObj(*n)),
+    fn evaluate_expr(&mut self, expr: &Expr) -> EvalRes {
+        //They can take half of the reserve, so a runaway recursion is
+        //stopped at the call and not at the expressions in its body
+        let res = match self.stack.left() > STACK_RESERVE / 2 {
+            true => self.evaluate_expr_kind(expr),
+            false => Err(RuntimeError::StackOverflow),
+        };
+        if let Err(e) = &res {
+            self.locate(e, expr.span);
+        }
+        res
+    }
+
+    fn evaluate_expr_kind(&mut self, expr: &Expr) -> EvalRes {
+        match &expr.kind {
+            ExprKind::NumberLit(n) => Ok(Object::NumberObj(*n)),
             //Todo: This is quite inefficient
-            Expr::StringLit(v) => return Ok(Object::StringObj(*v.clone())),
-            Expr::Unary(operator, expr) => return self.handle_unary(operator, expr),
-            Expr::Binary(left, op, right) => return self.handle_binary(op, left, right),
-            Expr::Boolean(v) => return Ok(Object::BoolObj(*v)),
-            Expr::Grouping(expr) => return self.evaluate_expr(expr), //This may be some kind of recursive
-            Expr::Nil => return Ok(Object::NullObj),
-            Expr::Variable(v) => return self.handle_variable_access(v),
-            Expr::Assignment(name, value) => self.handle_assignment(name, value),
-            Expr::Logical(left, operator, right) => self.handle_logical(left, operator, right),
+            ExprKind::StringLit(v) => Ok(Object::StringObj(*v.clone())),
+            ExprKind::Unary(operator, expr) => self.handle_unary(operator, expr),
+            ExprKind::Binary(left, op, right) => self.handle_binary(op, left, right),
+            ExprKind::Boolean(v) => Ok(Object::BoolObj(*v)),
+            ExprKind::Grouping(expr) => self.evaluate_expr(expr), //This may be some kind of recursive
+            ExprKind::Nil => Ok(Object::NullObj),
+            ExprKind::Variable(v, depth) => self.handle_variable_access(v, depth),
+            ExprKind::Assignment(name, value, depth) => self.handle_assignment(name, value, depth),
+            ExprKind::CompoundAssignment(name, operator, value, depth) => {
+                self.handle_compound_assignment(name, operator, value, depth)
+            }
+            ExprKind::Logical(left, operator, right) => self.handle_logical(left, operator, right),
+            //Only the chosen branch is evaluated
+            ExprKind::Conditional(condition, then, else_) => {
+                match self.evaluate_expr(condition)?.is_truthy(self.semantics)? {
+                    true => self.evaluate_expr(then),
+                    false => self.evaluate_expr(else_),
+                }
+            }
+            ExprKind::Call(callee, _, arguments) => self.handle_call(callee, arguments, expr.span),
+            ExprKind::Get(object, name) => self.handle_get(object, name),
+            ExprKind::Set(object, name, value) => self.handle_set(object, name, value),
+            ExprKind::CompoundSet(object, name, operator, value) => {
+                self.handle_compound_set(object, name, operator, value)
+            }
+            ExprKind::This(_, depth) => {
+                self.handle_variable_access(&Box::new(THIS.to_string()), depth)
+            }
+            ExprKind::Super(_, method, depth) => self.handle_super(method, depth),
         }
     }
 
+    //`super` lives in the scope right above the one holding `this`.
+    //The method is taken from the superclass, but bound to the current instance
+    fn handle_super(&mut self, method: &Token, depth: &Depth) -> EvalRes {
+        let distance = depth
+            .get()
+            .ok_or(RuntimeError::UndefinedVariable(SUPER.to_string()))?;
+        let superclass = match self.enviroment.get_at(distance, &SUPER.to_string()) {
+            Some(Object::ClassObj(c)) => c,
+            _ => return Err(RuntimeError::UndefinedVariable(SUPER.to_string())),
+        };
+        let this_distance = distance
+            .checked_sub(1)
+            .ok_or(RuntimeError::UndefinedVariable(THIS.to_string()))?;
+        let instance = match self.enviroment.get_at(this_distance, &THIS.to_string()) {
+            Some(Object::InstanceObj(i)) => i,
+            _ => return Err(RuntimeError::UndefinedVariable(THIS.to_string())),
+        };
+
+        match superclass.find_method(&method.lexeme) {
+            Some(m) => Ok(Object::FunctionObj(m.bind(instance))),
+            None => Err(RuntimeError::UndefinedProperty(method.lexeme.clone())),
+        }
+    }
+
+    fn handle_get(&mut self, object: &Expr, name: &Token) -> EvalRes {
+        match self.evaluate_expr(object)? {
+            Object::InstanceObj(instance) => instance.get(name),
+            o => Err(RuntimeError::TypeError(format!(
+                "Only instances have properties, got {:?}",
+                o
+            ))),
+        }
+    }
+
+    fn handle_set(&mut self, object: &Expr, name: &Token, value: &Expr) -> EvalRes {
+        let instance = match self.evaluate_expr(object)? {
+            Object::InstanceObj(instance) => instance,
+            o => {
+                return Err(RuntimeError::TypeError(format!(
+                    "Only instances have fields, got {:?}",
+                    o
+                )))
+            }
+        };
+
+        let value = self.evaluate_expr(value)?;
+        instance.set(name, value.clone());
+        Ok(value)
+    }
+
+    fn handle_compound_set(
+        &mut self,
+        object: &Expr,
+        name: &Token,
+        operator: &Token,
+        value: &Expr,
+    ) -> EvalRes {
+        let instance = match self.evaluate_expr(object)? {
+            Object::InstanceObj(instance) => instance,
+            o => {
+                return Err(RuntimeError::TypeError(format!(
+                    "Only instances have fields, got {:?}",
+                    o
+                )))
+            }
+        };
+
+        let current = instance.get(name)?;
+        let value = compound(operator, current, self.evaluate_expr(value)?)?;
+        instance.set(name, value.clone());
+        Ok(value)
+    }
+
+    fn handle_call(&mut self, callee: &Expr, arguments: &[Box<Expr>], call_site: Span) -> EvalRes {
+        let callee_value = self.evaluate_expr(callee)?;
+
+        let mut evaluated_arguments = Vec::with_capacity(arguments.len());
+        for argument in arguments {
+            evaluated_arguments.push(self.evaluate_expr(argument)?);
+        }
+
+        let function: &dyn Callable = match &callee_value {
+            Object::FunctionObj(f) => f,
+            Object::ClassObj(c) => c,
+            Object::NativeFunctionObj(f) => f,
+            _ => {
+                return Err(RuntimeError::TypeError(format!(
+                    "Can only call functions and classes, got {:?}",
+                    callee_value
+                )))
+            }
+        };
+
+        if evaluated_arguments.len() != function.arity() {
+            return Err(RuntimeError::ArityError(format!(
+                "Expected {} arguments but got {}",
+                function.arity(),
+                evaluated_arguments.len()
+            )));
+        }
+
+        if !self.stack.has_room() {
+            return Err(RuntimeError::StackOverflow);
+        }
+        self.frames.push(Frame {
+            function: function.name(),
+            call_site,
+        });
+        let res = function.call(self, evaluated_arguments);
+        self.frames.pop();
+        res
+    }
+
     fn handle_logical(
         &mut self,
-        left: &'a Box<Expr>,
+        left: &Box<Expr>,
         operator: &Box<Token>,
-        right: &'a Box<Expr>,
+        right: &Box<Expr>,
     ) -> EvalRes {
         let left_expr_value = self.evaluate_expr(left)?;
         match operator.token_type {
             TokenType::AND => {
-                if !left_expr_value.is_truthy() {
+                if !left_expr_value.is_truthy(self.semantics)? {
                     return Ok(left_expr_value);
                 }
                 return self.evaluate_expr(right);
             }
             TokenType::OR => {
-                if left_expr_value.is_truthy() {
+                if left_expr_value.is_truthy(self.semantics)? {
                     return Ok(left_expr_value);
                 }
                 return self.evaluate_expr(&right);
@@ -173,13 +645,47 @@ impl<'a> Interpreter<'a> {
         }
     }
 
-    fn handle_assignment(&mut self, name: &Box<String>, value: &'a Box<Expr>) -> EvalRes {
+    //The depth comes from the resolver. Locals are accessed directly in the
+    //scope where they live, and anything else is a global
+    fn handle_assignment(
+        &mut self,
+        name: &Box<String>,
+        value: &Box<Expr>,
+        depth: &Depth,
+    ) -> EvalRes {
         let v = self.evaluate_expr(&value)?;
-        self.enviroment.assign(name, v)
+        match depth.get() {
+            Some(distance) => self.enviroment.assign_at(distance, name, v),
+            None => self.globals.assign(name, v),
+        }
     }
 
-    fn handle_variable_access(&self, name: &Box<String>) -> EvalRes {
-        match self.enviroment.get(&name) {
+    //a += b is a = a + b, with the variable read before evaluating b
+    fn handle_compound_assignment(
+        &mut self,
+        name: &String,
+        operator: &Token,
+        value: &Expr,
+        depth: &Depth,
+    ) -> EvalRes {
+        let current = match depth.get() {
+            Some(distance) => self.enviroment.get_at(distance, name),
+            None => self.globals.get(name),
+        }
+        .ok_or_else(|| RuntimeError::UndefinedVariable(name.to_string()))?;
+        let v = compound(operator, current, self.evaluate_expr(value)?)?;
+        match depth.get() {
+            Some(distance) => self.enviroment.assign_at(distance, name, v),
+            None => self.globals.assign(name, v),
+        }
+    }
+
+    fn handle_variable_access(&self, name: &Box<String>, depth: &Depth) -> EvalRes {
+        let value = match depth.get() {
+            Some(distance) => self.enviroment.get_at(distance, name),
+            None => self.globals.get(name),
+        };
+        match value {
             None => {
                 return Err(RuntimeError::UndefinedVariable(name.to_string()));
             }
@@ -187,7 +693,7 @@ impl<'a> Interpreter<'a> {
         }
     }
 
-    fn handle_unary(&mut self, operator: &Box<Token>, expr: &'a Box<Expr>) -> EvalRes {
+    fn handle_unary(&mut self, operator: &Box<Token>, expr: &Box<Expr>) -> EvalRes {
         //This should be a number
         //Can it be forced?
         let evaluated_expression = self.evaluate_expr(&expr)?;
@@ -196,10 +702,13 @@ impl<'a> Interpreter<'a> {
                 let a = (-evaluated_expression)?;
                 return Ok(a);
             }
-            TokenType::BANG => {
-                let a = (!evaluated_expression)?;
-                return Ok(a);
-            }
+            //In the spec any value can be negated, ie. !nil is true
+            TokenType::BANG => match self.semantics {
+                Semantics::Spec => Ok(Object::BoolObj(
+                    !evaluated_expression.is_truthy(self.semantics)?,
+                )),
+                Semantics::Permissive => !evaluated_expression,
+            },
             _ => Err(RuntimeError::UnknownExpression(format!(
                 "Unexpected operator {:?} on unary expression",
                 operator.token_type
@@ -210,8 +719,8 @@ impl<'a> Interpreter<'a> {
     fn handle_binary(
         &mut self,
         operator: &Box<Token>,
-        left: &'a Box<Expr>,
-        right: &'a Box<Expr>,
+        left: &Box<Expr>,
+        right: &Box<Expr>,
     ) -> EvalRes {
         let left_evaluated = self.evaluate_expr(&left)?;
         let right_evaluated = self.evaluate_expr(&right)?;
@@ -237,3 +746,228 @@ impl<'a> Interpreter<'a> {
         res
     }
 }
+
+//The arithmetic of a compound assignment. Same type checks as the binary operators
+fn compound(operator: &Token, current: Object, value: Object) -> EvalRes {
+    match operator.token_type {
+        TokenType::PLUSEQUAL => current + value,
+        TokenType::MINUSEQUAL => current - value,
+        TokenType::STAREQUAL => current * value,
+        TokenType::SLASHEQUAL => current / value,
+        _ => Err(RuntimeError::UnknownExpression(format!(
+            "Unexpected token for compound assignment {:?}",
+            operator.token_type
+        ))),
+    }
+}
+
+#[cfg(test)]
+mod interpreter_tests {
+    use crate::{
+        ast::span::Span,
+        enviroment::Environment,
+        object::Object,
+        tests::helpers::{resolve_source, run_in},
+    };
+
+    use super::{Interpreter, RuntimeError, RuntimeFailure, STACK_RESERVE};
+
+    //Runs the whole program and returns the global scope to check the results
+    fn run(src: &str) -> Environment {
+        let enviroment = Environment::new();
+        _ = run_in(src, &enviroment);
+        enviroment
+    }
+
+    fn fail(src: &str) -> RuntimeFailure {
+        run_in(src, &Environment::new()).unwrap_err()
+    }
+
+    #[test]
+    fn failure_points_at_the_failing_expression() {
+        let failure = fail("var a = 1;\nprint a + (2 - \"x\");");
+        assert!(matches!(failure.error, RuntimeError::TypeError(_)));
+        assert_eq!(failure.span, Some(Span::new(0, 22, 29, 2, 12)));
+        assert!(failure.stack.is_empty());
+    }
+
+    #[test]
+    fn failure_keeps_the_call_stack() {
+        let failure = fail("fun f() {\n  return -\"a\";\n}\nfun g() { f(); }\ng();");
+        let names: Vec<&str> = failure.stack.iter().map(|f| f.function.as_str()).collect();
+        assert_eq!(names, vec!["g", "f"]);
+        assert_eq!(
+            failure.traceback(),
+            vec!["line 2, in f", "line 4, in g", "line 5, in <script>"]
+        );
+    }
+
+    #[test]
+    fn recursion_is_folded_in_the_traceback() {
+        let failure =
+            fail("fun f(n) {\n  if (n == 0) return -\"a\";\n  return f(n - 1);\n}\nf(3);");
+        assert_eq!(
+            failure.traceback(),
+            vec![
+                "line 2, in f",
+                "line 3, in f (3 times)",
+                "line 5, in <script>"
+            ]
+        );
+    }
+
+    #[test]
+    fn recursion_is_limited_by_the_stack_size() {
+        let src =
+            "fun count(n) { if (n == 0) return 0; return 1 + count(n - 1); } var x = count(100);";
+        let env = run(src);
+        assert_eq!(env.get(&"x".to_string()), Some(Object::NumberObj(100.0)));
+
+        let mut interpreter = Interpreter::new(&Environment::new());
+        interpreter.set_stack_size(STACK_RESERVE + 1024);
+        let failure = interpreter.interpret(&resolve_source(src)).unwrap_err();
+        assert_eq!(failure.error, RuntimeError::StackOverflow);
+    }
+
+    #[test]
+    fn statement_errors_are_located() {
+        let failure = fail("var a = 1;\nclass B < a {}");
+        assert_eq!(failure.span.map(|s| s.line), Some(2));
+    }
+
+    #[test]
+    fn call_function() {
+        let env = run("fun add(a, b) { return a + b; } var x = add(1, 2);");
+        assert_eq!(env.get(&"x".to_string()), Some(Object::NumberObj(3.0)));
+    }
+
+    #[test]
+    fn function_without_return_is_nil() {
+        let env = run("fun nothing() { var a = 1; } var x = nothing();");
+        assert_eq!(env.get(&"x".to_string()), Some(Object::NullObj));
+    }
+
+    #[test]
+    fn return_unwinds_nested_blocks() {
+        let env =
+            run("fun first(n) { while (true) { if (n > 0) { return n; } } } var x = first(4);");
+        assert_eq!(env.get(&"x".to_string()), Some(Object::NumberObj(4.0)));
+    }
+
+    #[test]
+    fn recursive_function() {
+        let env = run(
+            "fun fib(n) { if (n < 2) { return n; } return fib(n - 1) + fib(n - 2); } var x = fib(10);",
+        );
+        assert_eq!(env.get(&"x".to_string()), Some(Object::NumberObj(55.0)));
+    }
+
+    #[test]
+    fn closure_keeps_its_scope_alive() {
+        let env = run(
+            "fun makeCounter() { var i = 0; fun count() { i = i + 1; return i; } return count; }
+            var counter = makeCounter(); counter(); var x = counter();",
+        );
+        assert_eq!(env.get(&"x".to_string()), Some(Object::NumberObj(2.0)));
+    }
+
+    #[test]
+    fn closures_of_the_same_function_are_different() {
+        let env = run("fun makeCounter() { fun count() {} return count; }
+            var counter = makeCounter();
+            var x = makeCounter() == makeCounter(); var y = counter == counter;");
+        assert_eq!(env.get(&"x".to_string()), Some(Object::BoolObj(false)));
+        assert_eq!(env.get(&"y".to_string()), Some(Object::BoolObj(true)));
+    }
+
+    #[test]
+    fn closure_captures_declaration_scope() {
+        //The function must see the global a, not the one of the caller block
+        let env = run("var a = \"global\";
+            var x;
+            fun show() { return a; }
+            { var a = \"block\"; x = show(); }");
+        assert_eq!(
+            env.get(&"x".to_string()),
+            Some(Object::StringObj("global".to_string()))
+        );
+    }
+
+    #[test]
+    fn block_scope_is_removed() {
+        let env = run("var a = 1; { var a = 2; var b = 3; a = 4; }");
+        assert_eq!(env.get(&"a".to_string()), Some(Object::NumberObj(1.0)));
+        assert_eq!(env.get(&"b".to_string()), None);
+    }
+
+    #[test]
+    fn class_fields_and_methods() {
+        let env = run("class Point { init(x, y) { this.x = x; this.y = y; }
+            sum() { return this.x + this.y; } }
+            var p = Point(1, 2); p.y = 5; var x = p.sum();");
+        assert_eq!(env.get(&"x".to_string()), Some(Object::NumberObj(6.0)));
+    }
+
+    #[test]
+    fn bound_method_keeps_this() {
+        let env = run(
+            "class Box { init(v) { this.v = v; } get() { return this.v; } }
+            var getter = Box(3).get; var x = getter();",
+        );
+        assert_eq!(env.get(&"x".to_string()), Some(Object::NumberObj(3.0)));
+    }
+
+    #[test]
+    fn methods_bound_to_different_instances_are_different() {
+        let env = run("class A { m() {} } var a = A(); var b = A();
+            var x = a.m == b.m; var m = a.m; var y = m == m;");
+        assert_eq!(env.get(&"x".to_string()), Some(Object::BoolObj(false)));
+        assert_eq!(env.get(&"y".to_string()), Some(Object::BoolObj(true)));
+    }
+
+    #[test]
+    fn init_returns_instance() {
+        let env = run("class A { init() { this.a = 1; return; } }
+            var a = A(); var x = a.init() == a;");
+        assert_eq!(env.get(&"x".to_string()), Some(Object::BoolObj(true)));
+    }
+
+    #[test]
+    fn undefined_property_fails() {
+        let env = run("class A {} var x = A().missing;");
+        assert_eq!(env.get(&"x".to_string()), None);
+    }
+
+    #[test]
+    fn inherited_method() {
+        let env = run("class A { name() { return \"A\"; } } class B < A {}
+            var x = B().name();");
+        assert_eq!(
+            env.get(&"x".to_string()),
+            Some(Object::StringObj("A".to_string()))
+        );
+    }
+
+    #[test]
+    fn super_call() {
+        let env = run(
+            "class A { init(v) { this.v = v; } get() { return this.v; } }
+            class B < A { init(v) { super.init(v * 2); } get() { return super.get() + 1; } }
+            var x = B(2).get();",
+        );
+        assert_eq!(env.get(&"x".to_string()), Some(Object::NumberObj(5.0)));
+    }
+
+    #[test]
+    fn superclass_must_be_a_class() {
+        let env = run("var A = 1; class B < A {} var x = 1;");
+        assert_eq!(env.get(&"B".to_string()), None);
+        assert_eq!(env.get(&"x".to_string()), None);
+    }
+
+    #[test]
+    fn wrong_arity_fails() {
+        let env = run("fun one(a) { return a; } var x = one(1, 2);");
+        assert_eq!(env.get(&"x".to_string()), None);
+    }
+}
@@ -0,0 +1,31 @@
+//! Yet another Lox interpreter.
+//!
+//! The [`Lox`] struct is the entry point to embed the interpreter:
+//!
+//! ```
+//! use rulox::{Lox, Object};
+//!
+//! let mut lox = Lox::new();
+//! lox.set_global("base", 40.0);
+//! lox.register_native("two", 0, |_, _| Ok(Object::NumberObj(2.0)));
+//! let result = lox.eval("base + two();").unwrap();
+//! assert_eq!(result, Object::NumberObj(42.0));
+//! ```
+pub mod ast;
+pub mod callable;
+pub mod class;
+pub mod diagnostics;
+pub mod enviroment;
+pub mod formatter;
+pub mod interpreter;
+pub mod lox;
+pub mod native;
+pub mod object;
+pub mod resolver;
+pub mod stack;
+mod tests;
+pub mod typechecker;
+
+pub use interpreter::{Interpreter, RuntimeError, RuntimeFailure};
+pub use lox::{Lox, LoxError};
+pub use object::{Object, Semantics};
@@ -1,69 +1,839 @@
-use std::{fs, path::Path, process::exit};
+use std::{
+    cell::RefCell,
+    collections::HashMap,
+    fmt::Display,
+    fs,
+    io::{self, BufReader},
+    path::Path,
+    rc::Rc,
+};
 
 use crate::{
-    ast::{parser::Parser, printer::AstPrinter, scanner::Scanner},
+    ast::{
+        parser::{Parser, ParserError},
+        printer::AstPrinter,
+        scanner::Scanner,
+        span::Span,
+        stmt::Stmt,
+        token::{Comment, Token},
+    },
+    diagnostics::{Diagnostic, Renderer},
     enviroment::Environment,
-    interpreter::Interpreter,
+    formatter::Formatter,
+    interpreter::{Input, Interpreter, Output, RuntimeError, RuntimeFailure},
+    native::{builtins, NativeFunction},
+    object::{Object, Semantics},
+    resolver::{Resolver, ResolverError},
+    stack::DEFAULT_STACK_SIZE,
+    typechecker::{Type, TypeCheckError, TypeChecker},
 };
 
+#[derive(Debug, PartialEq)]
+pub struct ScanError {
+    pub span: Span,
+    pub message: String,
+}
+
+impl Display for ScanError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "[line {}:{}] Error : {}",
+            self.span.line, self.span.column, self.message
+        )
+    }
+}
+
+//Everything that can go wrong while running some source code.
+//Each stage stops the pipeline, so only one kind of error is returned
+#[derive(Debug)]
+pub enum LoxError {
+    Io(io::Error),
+    Scan(Vec<ScanError>),
+    Parse(Vec<ParserError>),
+    Resolve(Vec<ResolverError>),
+    Type(Vec<TypeCheckError>),
+    Runtime(RuntimeFailure),
+}
+
+impl Display for LoxError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Io(e) => write!(f, "[Error] - Could not read the source: {}", e),
+            Self::Scan(errors) => write_all(f, errors),
+            Self::Parse(errors) => write_all(f, errors),
+            Self::Resolve(errors) => write_all(f, errors),
+            Self::Type(errors) => write_all(f, errors),
+            Self::Runtime(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+fn write_all<T: Display>(f: &mut std::fmt::Formatter<'_>, errors: &[T]) -> std::fmt::Result {
+    for (i, e) in errors.iter().enumerate() {
+        if i > 0 {
+            writeln!(f)?;
+        }
+        write!(f, "{}", e)?;
+    }
+    Ok(())
+}
+
+impl std::error::Error for LoxError {}
+
+impl LoxError {
+    //Conventional exit status (see sysexits.h) for a process that fails with this error
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            Self::Io(_) => 66,
+            Self::Scan(_) | Self::Parse(_) | Self::Resolve(_) | Self::Type(_) => 65,
+            Self::Runtime(_) => 70,
+        }
+    }
+
+    //Every error found, ready to be rendered
+    pub fn diagnostics(&self) -> Vec<Diagnostic> {
+        match self {
+            //Not a problem of the code, so there is no stage code for it
+            Self::Io(e) => vec![Diagnostic::new(
+                "E0000",
+                format!("Could not read the source: {}", e),
+            )],
+            Self::Scan(errors) => errors.iter().map(Diagnostic::from).collect(),
+            Self::Parse(errors) => errors.iter().map(Diagnostic::from).collect(),
+            Self::Resolve(errors) => errors.iter().map(Diagnostic::from).collect(),
+            Self::Type(errors) => errors.iter().map(Diagnostic::from).collect(),
+            Self::Runtime(failure) => vec![Diagnostic::from(failure)],
+        }
+    }
+}
+
+type LoxResult<T> = Result<T, LoxError>;
+
+//Entry point for both the CLI and any host embedding the interpreter.
+//The global scope lives as long as the Lox instance, so every piece of
+//source evaluated on it shares the same globals
 pub struct Lox {
-    had_error: bool,
+    scan_errors: Vec<ScanError>,
+    globals: Environment,
+    //Static types of the globals, they are checked in every new source too
+    types: HashMap<String, Type>,
+    output: Output,
+    input: Input,
+    //Skips the echo of the source before running a file
+    quiet: bool,
+    //Errors are rendered with ANSI colors
+    color: bool,
+    semantics: Semantics,
+    stack_size: usize,
+    //Last compiled source, kept to show the snippets of its errors.
+    //Every source gets a new id, that ends up in the spans of its code
+    file_name: String,
+    source: String,
+    file: usize,
 }
 
 impl Default for Lox {
     fn default() -> Self {
-        Self { had_error: false }
+        let mut globals = Environment::new();
+        for native in builtins() {
+            //Defining in a fresh scope can not fail
+            _ = native.define_in(&mut globals);
+        }
+        Self {
+            scan_errors: Vec::new(),
+            globals,
+            types: HashMap::new(),
+            output: Rc::new(RefCell::new(io::stdout())),
+            input: Rc::new(RefCell::new(BufReader::new(io::stdin()))),
+            quiet: false,
+            color: false,
+            semantics: Semantics::default(),
+            stack_size: DEFAULT_STACK_SIZE,
+            file_name: String::from("<script>"),
+            source: String::new(),
+            file: 0,
+        }
     }
 }
 
 impl Lox {
-    pub fn run_file(&mut self, string_path: &String) {
-        let path = Path::new(string_path);
-        if !path.exists() {
-            println!("Error: There is no {} to interpretate", string_path);
-            return;
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    //Exposes a Rust closure to the scripts as a global function.
+    //If there is already a global with the same name, it is replaced
+    pub fn register_native<F>(&mut self, name: &str, arity: usize, function: F)
+    where
+        F: Fn(&mut Interpreter, Vec<Object>) -> Result<Object, RuntimeError> + 'static,
+    {
+        _ = NativeFunction::new(name, arity, function).define_in(&mut self.globals);
+        self.types.remove(name);
+    }
+
+    //Everything printed by the scripts (and the prompt) goes to this stream
+    pub fn set_output(&mut self, output: Output) {
+        self.output = output;
+    }
+
+    //Stream used by the prompt and by the input() builtin
+    pub fn set_input(&mut self, input: Input) {
+        self.input = input;
+    }
+
+    pub fn set_quiet(&mut self, quiet: bool) {
+        self.quiet = quiet;
+    }
+
+    pub fn set_color(&mut self, color: bool) {
+        self.color = color;
+    }
+
+    //Truthiness rules used by the scripts. The Lox book ones by default
+    pub fn set_semantics(&mut self, semantics: Semantics) {
+        self.semantics = semantics;
+    }
+
+    //Size of the stack of the thread that runs the scripts. The deepest
+    //recursion and nesting allowed depend on it
+    pub fn set_stack_size(&mut self, stack_size: usize) {
+        self.stack_size = stack_size;
+    }
+
+    //Renders the error with the source it comes from. It must be called
+    //before running anything else, because only the last source is kept.
+    //Code from an older source (ie. a function defined in a previous input
+    //of the prompt) is located, but its snippet is not shown
+    pub fn render_error(&self, error: &LoxError) -> String {
+        let renderer = match self.color {
+            true => Renderer::colored(),
+            false => Renderer::plain(),
+        };
+        error
+            .diagnostics()
+            .iter()
+            .map(|d| match d.span {
+                Some(span) if span.file != self.file => renderer.render(d, &self.file_name, ""),
+                _ => renderer.render(d, &self.file_name, &self.source),
+            })
+            .collect::<Vec<String>>()
+            .join("\n")
+    }
+
+    pub fn get_global(&self, name: &str) -> Option<Object> {
+        self.globals.get(&name.to_string())
+    }
+
+    //The value replaces any static type the global had, the checker
+    //knows nothing about it
+    pub fn set_global<T: Into<Object>>(&mut self, name: &str, value: T) {
+        _ = self.globals.define(&name.to_string(), Some(value.into()));
+        self.types.remove(name);
+    }
+
+    //Runs the source and returns the value of the last statement,
+    //if it is an expression statement. Otherwise returns nil
+    pub fn eval(&mut self, source: &str) -> LoxResult<Object> {
+        let (statements, types) = self.compile(source)?;
+        let result = self.interpreter().interpret(&statements);
+        self.keep_types(types, result.is_ok());
+        result.map_err(LoxError::Runtime)
+    }
+
+    pub fn run_file(&mut self, string_path: &String) -> LoxResult<()> {
+        let content = fs::read_to_string(Path::new(string_path)).map_err(LoxError::Io)?;
+        self.file_name = string_path.clone();
+        self.run(content)
+    }
+
+    //The prompt keeps using the same global scope for the whole session, so
+    //variables defined in one input are visible in the following ones.
+    //If the input has unclosed ( or {, the prompt keeps reading lines
+    //until everything is balanced (or an empty line is entered) and only
+    //then runs the whole chunk
+    pub fn run_prompt(&mut self) {
+        let mut buffer = String::new();
+        self.file_name = String::from("<prompt>");
+
+        loop {
+            let prompt = if buffer.is_empty() { "> " } else { ". " };
+            self.write(format_args!("{prompt}"));
+
+            let mut line = String::new();
+            let read = self.input.borrow_mut().read_line(&mut line);
+            match read {
+                //Ctrl-D. An input still open is run as it is, as with an empty line
+                Ok(0) => {
+                    self.write(format_args!("\n"));
+                    if !buffer.is_empty() {
+                        self.run_input(&buffer);
+                    }
+                    break;
+                }
+                Ok(_) => {}
+                Err(e) => {
+                    self.write(format_args!("Error: Could not read the input: {e}\n"));
+                    break;
+                }
+            }
+
+            //An empty line forces the execution, even if something is
+            //still open. Otherwise a typo would trap the user forever
+            let force = !buffer.is_empty() && line.trim().is_empty();
+            buffer.push_str(&line);
+            if !force && open_delimiters(&buffer) > 0 {
+                continue;
+            }
+
+            let content = std::mem::take(&mut buffer);
+            self.run_input(&content);
         }
-        let content = fs::read_to_string(string_path).unwrap();
-        self.run(content);
-    }
-
-    //Todo: Implement prompt. At the moment im not sure
-    // if i want to run command line by line or support
-    // multiples lines and enter an specific command to run
-    pub fn run_prompt(&self) {
-        println!("> ");
-        todo!();
-    }
-
-    pub fn run(&mut self, content: String) {
-        println!("Content:\n{content}");
-        println!("------------------ \n");
-        //Run scanner
-        let mut scanner = Scanner::new(self, &content);
-        let tokens = scanner.scan_tokens();
-        //Run parser
-        let mut parser = Parser::new(tokens);
-        let stmts = parser.parse();
-        if let Err(e) = stmts {
-            println!("{}", e);
-            exit(1);
-        }
-        let statements = stmts.unwrap();
-        // println!("Statements: {:?}", &statements);
-        // let _ast_str = AstPrinter::default().print_program(&statements);
-        // println!("AST -> {ast_str}");
-
-        let mut enviroment = Environment::new();
+    }
+
+    //Any error is reported, but the session must go on
+    fn run_input(&mut self, content: &str) {
+        if let Err(e) = self.run_line(content) {
+            let rendered = self.render_error(&e);
+            self.write(format_args!("{rendered}"));
+        }
+    }
+
+    pub fn run(&mut self, content: String) -> LoxResult<()> {
+        if !self.quiet {
+            self.write(format_args!("Content:\n{content}\n"));
+            self.write(format_args!("------------------ \n\n"));
+        }
+        let (statements, types) = self.compile(&content)?;
+
         //Run the code
-        Interpreter::new(&mut enviroment).interpret(&statements);
+        let result = self.interpreter().interpret(&statements);
+        self.keep_types(types, result.is_ok());
+        result.map_err(LoxError::Runtime)?;
+        Ok(())
+    }
+
+    //Same pipeline as run, but the value of bare expression statements is echoed back
+    fn run_line(&mut self, content: &str) -> LoxResult<()> {
+        let (statements, types) = self.compile(content)?;
+        let result = self.interpreter().interpret_echo(&statements);
+        self.keep_types(types, result.is_ok());
+        result.map_err(LoxError::Runtime)
+    }
+
+    //Keeps the static types of the globals once the code has run. If it
+    //stopped halfway, it is not known which of its declarations and
+    //assignments ran: the new globals are left out, and the old ones
+    //whose type it changed can have either
+    fn keep_types(&mut self, types: HashMap<String, Type>, completed: bool) {
+        if completed {
+            self.types = types;
+            return;
+        }
+        for (name, type_) in self.types.iter_mut() {
+            if types.get(name) != Some(type_) {
+                *type_ = Type::Any;
+            }
+        }
+    }
+
+    fn interpreter(&self) -> Interpreter {
+        let mut interpreter =
+            Interpreter::with_streams(&self.globals, self.output.clone(), self.input.clone());
+        interpreter.set_semantics(self.semantics);
+        interpreter.set_stack_size(self.stack_size);
+        interpreter
+    }
+
+    //Messages of the prompt itself. If the output is broken there is
+    //nobody to tell, so the error is ignored
+    fn write(&self, message: std::fmt::Arguments) {
+        let mut output = self.output.borrow_mut();
+        _ = output.write_fmt(message);
+        _ = output.flush();
+    }
+
+    //Parses the file and returns its AST as S-expressions, one line for
+    //every top level statement. Nothing is resolved or run
+    pub fn dump_ast(&mut self, string_path: &String) -> LoxResult<String> {
+        let content = fs::read_to_string(Path::new(string_path)).map_err(LoxError::Io)?;
+        self.file_name = string_path.clone();
+        let statements = self.parse(&content)?;
+        Ok(AstPrinter.print_program(&statements))
+    }
+
+    //Scans the file and returns every token, with the comments in between,
+    //one per line as TYPE - lexeme - line:column. With json it is an array
+    //of {"type", "lexeme", "line", "column"} objects instead
+    pub fn dump_tokens(&mut self, string_path: &String, json: bool) -> LoxResult<String> {
+        let content = fs::read_to_string(Path::new(string_path)).map_err(LoxError::Io)?;
+        self.file_name = string_path.clone();
+        let (tokens, comments) = self.scan(&content)?;
+        let mut entries: Vec<(String, &str, &Span)> = tokens
+            .iter()
+            //Taken from the source, the lexeme of a string has lost its quotes
+            .map(|t| {
+                let written = content.get(t.span.start..t.span.end);
+                (t.token_type.name(), written.unwrap_or(&t.lexeme), &t.span)
+            })
+            .chain(
+                comments
+                    .iter()
+                    .map(|c| (String::from("COMMENT"), c.text.as_str(), &c.span)),
+            )
+            .collect();
+        entries.sort_by_key(|(_, _, span)| span.start);
+
+        if !json {
+            let lines: Vec<String> = entries
+                .iter()
+                .map(|(name, lexeme, span)| {
+                    //Strings and comments can take several lines
+                    let lexeme = lexeme.replace('\n', "\\n");
+                    format!("{} - {} - {}:{}", name, lexeme, span.line, span.column)
+                })
+                .collect();
+            return Ok(lines.join("\n"));
+        }
+        let objects: Vec<String> = entries
+            .iter()
+            .map(|(name, lexeme, span)| {
+                format!(
+                    "  {{\"type\": \"{}\", \"lexeme\": {}, \"line\": {}, \"column\": {}}}",
+                    name,
+                    json_string(lexeme),
+                    span.line,
+                    span.column
+                )
+            })
+            .collect();
+        Ok(format!("[\n{}\n]", objects.join(",\n")))
+    }
+
+    //Returns the file in the canonical style. The file is not modified
+    pub fn format_file(&mut self, string_path: &String) -> LoxResult<String> {
+        let content = fs::read_to_string(Path::new(string_path)).map_err(LoxError::Io)?;
+        self.file_name = string_path.clone();
+        self.format(&content)
+    }
+
+    //Same as format_file, for a source that is already in memory.
+    //It must be valid code, the formatter works over the AST
+    pub fn format(&mut self, source: &str) -> LoxResult<String> {
+        let source = source.to_string();
+        let (tokens, comments) = self.scan(&source)?;
+        let statements = Parser::new(&tokens).parse().map_err(LoxError::Parse)?;
+        Ok(Formatter::new(&source, &comments).format(&statements))
+    }
+
+    //The comments are returned apart from the tokens
+    fn scan(&mut self, content: &str) -> LoxResult<(Vec<Token>, Vec<Comment>)> {
+        self.source = content.to_string();
+        self.file += 1;
+        let mut scanner = Scanner::for_file(self, content, self.file);
+        let tokens = scanner.scan_tokens().clone();
+        let comments = scanner.comments().clone();
+        if !self.scan_errors.is_empty() {
+            return Err(LoxError::Scan(std::mem::take(&mut self.scan_errors)));
+        }
+        Ok((tokens, comments))
+    }
+
+    //Scanner and parser
+    fn parse(&mut self, content: &str) -> LoxResult<Vec<Stmt>> {
+        let (tokens, _) = self.scan(content)?;
+        let mut parser = Parser::new(&tokens);
+        parser.set_stack_size(self.stack_size);
+        parser.parse().map_err(LoxError::Parse)
+    }
+
+    //Scanner, parser, resolver and type checker. Everything that happens before running the code.
+    //The static types of the globals are returned too, they are kept only after running it
+    fn compile(&mut self, content: &str) -> LoxResult<(Vec<Stmt>, HashMap<String, Type>)> {
+        let statements = self.parse(content)?;
+        let mut resolver = Resolver::new();
+        resolver.set_stack_size(self.stack_size);
+        resolver.resolve(&statements).map_err(LoxError::Resolve)?;
+        let mut checker = TypeChecker::with_globals(self.types.clone());
+        checker.set_stack_size(self.stack_size);
+        checker.check(&statements).map_err(LoxError::Type)?;
+        Ok((statements, checker.into_globals()))
+    }
+
+    pub fn error(&mut self, span: Span, message: &str) {
+        self.scan_errors.push(ScanError {
+            span,
+            message: message.to_string(),
+        });
+    }
+}
+
+//The text as a JSON string literal, quoted and escaped
+fn json_string(text: &str) -> String {
+    let mut out = String::from("\"");
+    for c in text.chars() {
+        match c {
+            '"' => out += "\\\"",
+            '\\' => out += "\\\\",
+            '\n' => out += "\\n",
+            '\r' => out += "\\r",
+            '\t' => out += "\\t",
+            c if c.is_control() => out += &format!("\\u{:04x}", c as u32),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+//Counts how many ( and { are still waiting to be closed. Strings and
+//comments are skipped so a "{" literal does not keep the prompt open
+fn open_delimiters(src: &str) -> i32 {
+    let mut depth = 0;
+    let mut chars = src.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '(' | '{' => depth += 1,
+            ')' | '}' => depth -= 1,
+            '"' => {
+                for s in chars.by_ref() {
+                    if s == '"' {
+                        break;
+                    }
+                }
+            }
+            '/' if chars.peek() == Some(&'/') => {
+                for s in chars.by_ref() {
+                    if s == '\n' {
+                        break;
+                    }
+                }
+            }
+            //Block comments are not nested, the first */ closes them
+            '/' if chars.peek() == Some(&'*') => {
+                chars.next();
+                let mut previous = ' ';
+                for s in chars.by_ref() {
+                    if previous == '*' && s == '/' {
+                        break;
+                    }
+                    previous = s;
+                }
+            }
+            _ => {}
+        }
+    }
+    depth
+}
+
+#[cfg(test)]
+mod lox_tests {
+    use super::{open_delimiters, Lox};
+    use crate::{
+        interpreter::RuntimeError,
+        object::{Object, Semantics},
+    };
+
+    use super::LoxError;
+    use std::{cell::RefCell, io::Cursor, rc::Rc};
+
+    #[test]
+    fn registered_native_is_callable() {
+        let mut lox = Lox::new();
+        lox.register_native("double", 1, |_, args| match &args[0] {
+            Object::NumberObj(n) => Ok(Object::NumberObj(n * 2.0)),
+            _ => Ok(Object::NullObj),
+        });
+        assert_eq!(lox.eval("double(21);").unwrap(), Object::NumberObj(42.0));
+        assert!(lox.get_global("clock").is_some());
+    }
+
+    #[test]
+    fn eval_returns_last_expression() {
+        let mut lox = Lox::new();
+        assert_eq!(
+            lox.eval("var a = 1; a + 1;").unwrap(),
+            Object::NumberObj(2.0)
+        );
+        assert_eq!(lox.eval("var b = 1;").unwrap(), Object::NullObj);
+    }
+
+    #[test]
+    fn globals_are_shared_with_the_host() {
+        let mut lox = Lox::new();
+        lox.set_global("name", "rulox");
+        lox.eval("var greeting = \"hi \" + name;").unwrap();
+        let greeting: String = lox.get_global("greeting").unwrap().try_into().unwrap();
+        assert_eq!(greeting, "hi rulox");
+    }
+
+    #[test]
+    fn consts_from_a_previous_eval_are_checked_at_runtime() {
+        let mut lox = Lox::new();
+        lox.eval("const answer = 42;").unwrap();
+        match lox.eval("answer = 1;") {
+            Err(LoxError::Runtime(failure)) => assert_eq!(
+                failure.error,
+                RuntimeError::ConstAssignment("answer".to_string())
+            ),
+            r => panic!("Expected a runtime error, got {:?}", r),
+        }
+        assert_eq!(lox.get_global("answer"), Some(Object::NumberObj(42.0)));
+    }
+
+    #[test]
+    fn errors_are_returned() {
+        let mut lox = Lox::new();
+        assert!(matches!(lox.eval("print ;"), Err(LoxError::Parse(_))));
+        assert!(matches!(
+            lox.eval("{ var a = a; }"),
+            Err(LoxError::Resolve(_))
+        ));
+        assert!(matches!(lox.eval("-\"a\";"), Err(LoxError::Type(_))));
+        assert!(matches!(
+            lox.eval("var s = \"a\"; -s;"),
+            Err(LoxError::Runtime(_))
+        ));
+        //The instance is still usable after an error
+        assert_eq!(lox.eval("1;").unwrap(), Object::NumberObj(1.0));
+    }
+
+    #[test]
+    fn errors_are_rendered_with_the_source() {
+        let mut lox = Lox::new();
+        let err = lox.eval("var a = 1;\nprint a +;").unwrap_err();
+        let rendered = lox.render_error(&err);
+        assert!(rendered.starts_with("error[E0100]: Unexpected token"));
+        assert!(rendered.contains(" --> <script>:2:10\n"));
+        assert!(rendered.contains("2 | print a +;\n  |          ^\n"));
+    }
+
+    #[test]
+    fn errors_in_an_older_source_have_no_snippet() {
+        let mut lox = Lox::new();
+        lox.eval("fun f(a) {\n  return 1 + a;\n}").unwrap();
+        let err = lox.eval("f(nil);").unwrap_err();
+        let rendered = lox.render_error(&err);
+        assert!(rendered.contains(" --> <script>:2:"));
+        assert!(!rendered.contains("f(nil);"));
+        assert!(!rendered.contains("^"));
+    }
+
+    #[test]
+    fn resolver_errors_are_located() {
+        let mut lox = Lox::new();
+        let err = lox.eval("{ var a = a; }").unwrap_err();
+        let rendered = lox.render_error(&err);
+        assert!(rendered.starts_with("error[E0200]: Can't read local variable a"));
+        assert!(rendered.contains(" --> <script>:1:11\n"));
+        assert!(rendered.contains("1 | { var a = a; }\n  |           ^\n"));
+    }
+
+    #[test]
+    fn global_types_are_kept_between_inputs() {
+        let mut lox = Lox::new();
+        lox.eval("var a: number = 1; fun f(s: string) {}").unwrap();
+        assert!(matches!(lox.eval("a = \"s\";"), Err(LoxError::Type(_))));
+        assert!(matches!(lox.eval("f(1);"), Err(LoxError::Type(_))));
+        //Rejected sources are not run, neither are their types kept
+        assert!(lox.eval("var b: string = \"b\"; print 1 - \"1\";").is_err());
+        assert!(matches!(lox.eval("b = 1;"), Err(LoxError::Runtime(_))));
+        assert_eq!(lox.eval("a = 2; a;").unwrap(), Object::NumberObj(2.0));
+    }
+
+    #[test]
+    fn global_types_are_kept_only_after_running() {
+        let mut lox = Lox::new();
+        lox.eval("fun f(s: string) {} fun g(n: number) { return n - 1; }")
+            .unwrap();
+        assert!(lox.eval("f = g; var x; x - 1; var c: number = 1;").is_err());
+        //f may be any of both, and c was never defined
+        assert!(lox.eval("f(1);").is_ok());
+        assert!(matches!(lox.eval("f(\"s\");"), Err(LoxError::Runtime(_))));
+        assert!(matches!(lox.eval("c = \"s\";"), Err(LoxError::Runtime(_))));
+    }
+
+    #[test]
+    fn host_globals_forget_the_static_types() {
+        let mut lox = Lox::new();
+        lox.eval("var x: number = 1; fun f(n: number) {}").unwrap();
+        lox.set_global("x", "s");
+        assert_eq!(
+            lox.eval("x + \"a\";").unwrap(),
+            Object::StringObj("sa".to_string())
+        );
+        lox.register_native("f", 1, |_, _| Ok(Object::NullObj));
+        assert!(lox.eval("f(\"s\");").is_ok());
+    }
+
+    #[test]
+    fn exit_codes() {
+        let mut lox = Lox::new();
+        assert_eq!(lox.eval("print ;").unwrap_err().exit_code(), 65);
+        assert_eq!(lox.eval("return 1;").unwrap_err().exit_code(), 65);
+        assert_eq!(lox.eval("-\"a\";").unwrap_err().exit_code(), 65);
+        assert_eq!(lox.eval("var s = \"a\"; -s;").unwrap_err().exit_code(), 70);
+    }
+
+    //Runs the source quietly and returns what it printed
+    fn printed(lox: &mut Lox, source: &str) -> String {
+        let output = Rc::new(RefCell::new(Vec::<u8>::new()));
+        lox.set_output(output.clone());
+        lox.set_quiet(true);
+        lox.run(source.to_string()).unwrap();
+        let printed = String::from_utf8_lossy(&output.borrow()).to_string();
+        printed
+    }
+
+    #[test]
+    fn spec_semantics() {
+        let mut lox = Lox::new();
+        let source =
+            "if (0) { print \"0\"; } if (\"\") { print \"empty\"; } if (nil) { print nil; }
+            print !nil; print !0; print nil or \"default\"; print 1 == \"1\"; print nil == false;
+            print nil; print 3; print 2.5; print -0.5 * 2; fun f() {} print f() == nil;";
+        assert_eq!(
+            printed(&mut lox, source),
+            "0\nempty\ntrue\nfalse\ndefault\nfalse\nfalse\nnil\n3\n2.5\n-1\ntrue\n"
+        );
+    }
+
+    #[test]
+    fn permissive_semantics() {
+        let mut lox = Lox::new();
+        lox.set_semantics(Semantics::Permissive);
+        assert_eq!(
+            printed(
+                &mut lox,
+                "if (0) { print 1; } if (\"\") { print 2; } print 0 or \"a\"; print nil;"
+            ),
+            "a\nNil\n"
+        );
+        assert!(matches!(lox.eval("!nil;"), Err(LoxError::Runtime(_))));
+        assert!(matches!(lox.eval("if (nil) {}"), Err(LoxError::Runtime(_))));
+    }
+
+    #[test]
+    fn dump_ast_does_not_run_the_script() {
+        let path = std::env::temp_dir().join("rulox_dump_ast.lox");
+        std::fs::write(&path, "var a = 1;\nprint a + b;").unwrap();
+        let mut lox = Lox::new();
+        let ast = lox.dump_ast(&path.to_string_lossy().to_string()).unwrap();
+        assert_eq!(ast, "(var a = 1)\n(print (+ a b))");
+        assert_eq!(lox.get_global("a"), None);
+    }
+
+    #[test]
+    fn dump_tokens_with_comments() {
+        let path = std::env::temp_dir().join("rulox_dump_tokens.lox");
+        std::fs::write(&path, "print \"a\"; /* x\n*/ b").unwrap();
+        let path = path.to_string_lossy().to_string();
+        let mut lox = Lox::new();
+        assert_eq!(
+            lox.dump_tokens(&path, false).unwrap(),
+            "PRINT - print - 1:1\n\
+             STRING - \"a\" - 1:7\n\
+             SEMICOLON - ; - 1:10\n\
+             COMMENT - /* x\\n*/ - 1:12\n\
+             IDENTIFIER - b - 2:4\n\
+             EOF -  - 2:5"
+        );
+        let json = lox.dump_tokens(&path, true).unwrap();
+        assert!(json.starts_with(
+            "[\n  {\"type\": \"PRINT\", \"lexeme\": \"print\", \"line\": 1, \"column\": 1},\n"
+        ));
+        assert!(json.contains(
+            "{\"type\": \"STRING\", \"lexeme\": \"\\\"a\\\"\", \"line\": 1, \"column\": 7},\n"
+        ));
+        assert!(json.contains(
+            "{\"type\": \"COMMENT\", \"lexeme\": \"/* x\\n*/\", \"line\": 1, \"column\": 12},\n"
+        ));
+        assert!(
+            json.ends_with("{\"type\": \"EOF\", \"lexeme\": \"\", \"line\": 2, \"column\": 5}\n]")
+        );
+    }
+
+    #[test]
+    fn json_strings_are_escaped() {
+        assert_eq!(
+            super::json_string("a\"b\\c\td\u{1}"),
+            "\"a\\\"b\\\\c\\td\\u0001\""
+        );
+    }
+
+    #[test]
+    fn output_is_captured() {
+        let output = Rc::new(RefCell::new(Vec::<u8>::new()));
+        let mut lox = Lox::new();
+        lox.set_output(output.clone());
+        lox.set_quiet(true);
+        lox.run("print 1 + 2; print \"done\";".to_string()).unwrap();
+        assert_eq!(String::from_utf8_lossy(&output.borrow()), "3\ndone\n");
+    }
+
+    #[test]
+    fn input_builtin_reads_from_the_input_stream() {
+        let mut lox = Lox::new();
+        lox.set_input(Rc::new(RefCell::new(Cursor::new("rulox\n"))));
+        let name: String = lox.eval("input();").unwrap().try_into().unwrap();
+        assert_eq!(name, "rulox");
+        assert_eq!(lox.eval("input();").unwrap(), Object::NullObj);
+    }
+
+    #[test]
+    fn prompt_session() {
+        let output = Rc::new(RefCell::new(Vec::<u8>::new()));
+        let mut lox = Lox::new();
+        lox.set_output(output.clone());
+        lox.set_input(Rc::new(RefCell::new(Cursor::new(
+            "var a = 1;\nif (a == 1) {\nprint \"one\";\n}\nprint b;\na + 1;\n",
+        ))));
+        lox.run_prompt();
+        assert_eq!(
+            String::from_utf8_lossy(&output.borrow()),
+            "> > . . one\n> error[E0303]: Undefined variable b\n \
+             --> <prompt>:1:7\n  \
+             |\n\
+             1 | print b;\n  \
+             |       ^\n  \
+             = help: declare it with var before using it\n> 2\n> \n"
+        );
+
+        //The input still open at the end is not lost
+        output.borrow_mut().clear();
+        lox.set_input(Rc::new(RefCell::new(Cursor::new("{ print 1;\n"))));
+        lox.run_prompt();
+        assert_eq!(
+            String::from_utf8_lossy(&output.borrow()),
+            "> . \nerror[E0100]: Unexpected token - Expected RIGHTBRACE, got EOF\n \
+             --> <prompt>:2:1\n  \
+             |\n\
+             2 | \n  \
+             | ^\n"
+        );
+    }
+
+    #[test]
+    fn balanced_input() {
+        assert_eq!(open_delimiters("if (x) { print x; }"), 0);
+    }
+
+    #[test]
+    fn unclosed_block() {
+        assert_eq!(open_delimiters("while (x < 3) {\n print x;\n"), 1);
     }
 
-    pub fn error(&mut self, line: u8, message: &str) {
-        self.report(line, "", message);
+    #[test]
+    fn ignore_delimiters_in_strings_and_comments() {
+        assert_eq!(open_delimiters("print \"{(\"; // {"), 0);
     }
 
-    fn report(&mut self, line: u8, place: &str, message: &str) {
-        println!("[line {line}] Error {place} : {message}");
-        self.had_error = true;
+    #[test]
+    fn ignore_delimiters_in_block_comments() {
+        assert_eq!(open_delimiters("/* { */"), 0);
+        assert_eq!(open_delimiters("print (1 /* ) */"), 1);
+        assert_eq!(open_delimiters("{ /* } \n } */ print 1;"), 1);
+        //The / that closes it is not the start of another comment
+        assert_eq!(open_delimiters("/**/{"), 1);
     }
 }
@@ -1,23 +1,119 @@
-mod ast;
-mod enviroment;
-pub mod interpreter;
-mod lox;
-mod object;
-mod tests;
+use rulox::{Lox, LoxError, Semantics};
+use std::{
+    env, fs,
+    io::{self, IsTerminal},
+    thread,
+};
 
-use crate::lox::Lox;
-use std::env;
+//The scripts run in their own thread with this stack, so a deep (but
+//finite) recursion does not hit the stack overflow error
+const STACK_SIZE: usize = 256 * 1024 * 1024;
+
+//Removes the flag from the arguments, returning if it was there
+fn take_flag(args: &mut Vec<String>, names: &[&str]) -> bool {
+    match args.iter().position(|a| names.contains(&a.as_str())) {
+        Some(pos) => {
+            args.remove(pos);
+            true
+        }
+        None => false,
+    }
+}
+
+fn fail(l: &Lox, e: LoxError) -> ! {
+    eprint!("{}", l.render_error(&e));
+    std::process::exit(e.exit_code())
+}
+
+//rulox fmt [--check] files...
+//Rewrites the files in the canonical style. With --check nothing is
+//written, and it fails if some file would change
+fn format_files(l: &mut Lox, files: &[String], check: bool) {
+    if files.is_empty() {
+        println!("Usage: lox fmt [--check] files...");
+        std::process::exit(64)
+    }
+
+    let mut unformatted = false;
+    for file in files {
+        let formatted = match l.format_file(file) {
+            Ok(formatted) => formatted,
+            Err(e) => fail(l, e),
+        };
+        if fs::read_to_string(file).is_ok_and(|original| original == formatted) {
+            continue;
+        }
+        if check {
+            println!("{} is not formatted", file);
+            unformatted = true;
+        } else if let Err(e) = fs::write(file, formatted) {
+            eprintln!("Could not write {}: {}", file, e);
+            std::process::exit(74)
+        }
+    }
+    if unformatted {
+        std::process::exit(1)
+    }
+}
 
 fn main() {
-    let args: Vec<String> = env::args().collect();
+    let runner = thread::Builder::new()
+        .stack_size(STACK_SIZE)
+        .spawn(run)
+        .expect("Could not start the interpreter thread");
+    if let Err(panic) = runner.join() {
+        std::panic::resume_unwind(panic)
+    }
+}
+
+fn run() {
+    let mut args: Vec<String> = env::args().collect();
     let mut l = Lox::default();
+    l.set_stack_size(STACK_SIZE);
+    l.set_color(io::stdout().is_terminal() && io::stderr().is_terminal());
+    //--quiet skips the echo of the script before running it
+    if take_flag(&mut args, &["--quiet", "-q"]) {
+        l.set_quiet(true);
+    }
+    //--permissive keeps the truthiness rules rulox had before following the book
+    if take_flag(&mut args, &["--permissive"]) {
+        l.set_semantics(Semantics::Permissive);
+    }
+    if args.get(1).is_some_and(|a| a == "fmt") {
+        let check = take_flag(&mut args, &["--check"]);
+        return format_files(&mut l, &args[2..], check);
+    }
+    //rulox tokens [--json] file prints what the scanner sees
+    if args.get(1).is_some_and(|a| a == "tokens") {
+        let json = take_flag(&mut args, &["--json"]);
+        if args.len() != 3 {
+            println!("Usage: lox tokens [--json] file");
+            std::process::exit(64)
+        }
+        match l.dump_tokens(&args[2], json) {
+            Ok(tokens) => println!("{}", tokens),
+            Err(e) => fail(&l, e),
+        }
+        return;
+    }
+    //--dump-ast prints the parsed script instead of running it
+    let dump_ast = take_flag(&mut args, &["--dump-ast"]);
 
     match args.len() {
-        2 => l.run_file(&args[1]),
-        1 => l.run_file(&"./test.txt".to_string()),
-        // 1 => l.run_prompt(),
+        2 if dump_ast => match l.dump_ast(&args[1]) {
+            Ok(ast) => println!("{}", ast),
+            Err(e) => fail(&l, e),
+        },
+        2 => {
+            if let Err(e) = l.run_file(&args[1]) {
+                fail(&l, e)
+            }
+        }
+        1 if !dump_ast => l.run_prompt(),
         _ => {
-            println!("Usage: lox [script]");
+            println!("Usage: lox [--quiet] [--permissive] [--dump-ast] [script]");
+            println!("       lox fmt [--check] files...");
+            println!("       lox tokens [--json] file");
             std::process::exit(64)
         }
     }
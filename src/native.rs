@@ -0,0 +1,227 @@
+use std::{
+    fmt::{Debug, Display},
+    rc::Rc,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use crate::{
+    callable::Callable,
+    enviroment::Environment,
+    interpreter::{Interpreter, RuntimeError},
+    object::Object,
+};
+
+type NativeResult = Result<Object, RuntimeError>;
+pub type NativeFn = dyn Fn(&mut Interpreter, Vec<Object>) -> NativeResult;
+
+//A function implemented in Rust and exposed to the scripts as a global.
+//The arity is checked by the interpreter before calling it, so the
+//closure can index the arguments without checking the length
+#[derive(Clone)]
+pub struct NativeFunction {
+    name: String,
+    arity: usize,
+    function: Rc<NativeFn>,
+}
+
+impl NativeFunction {
+    pub fn new<F>(name: &str, arity: usize, function: F) -> Self
+    where
+        F: Fn(&mut Interpreter, Vec<Object>) -> NativeResult + 'static,
+    {
+        Self {
+            name: name.to_string(),
+            arity,
+            function: Rc::new(function),
+        }
+    }
+
+    pub fn name(&self) -> &String {
+        &self.name
+    }
+
+    //Defines the function as a global of the received environment
+    pub fn define_in(&self, enviroment: &mut Environment) -> Result<(), RuntimeError> {
+        enviroment.define(&self.name, Some(Object::NativeFunctionObj(self.clone())))
+    }
+}
+
+impl Callable for NativeFunction {
+    fn name(&self) -> String {
+        self.name.clone()
+    }
+
+    fn arity(&self) -> usize {
+        self.arity
+    }
+
+    fn call(&self, interpreter: &mut Interpreter, arguments: Vec<Object>) -> NativeResult {
+        (self.function)(interpreter, arguments)
+    }
+}
+
+impl PartialEq for NativeFunction {
+    fn eq(&self, other: &Self) -> bool {
+        Rc::ptr_eq(&self.function, &other.function)
+    }
+}
+
+impl PartialOrd for NativeFunction {
+    fn partial_cmp(&self, _other: &Self) -> Option<std::cmp::Ordering> {
+        None
+    }
+}
+
+impl Debug for NativeFunction {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "NativeFunction({})", self.name)
+    }
+}
+
+impl Display for NativeFunction {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "<native fn {}>", self.name)
+    }
+}
+
+//The functions every script has available without declaring them
+pub fn builtins() -> Vec<NativeFunction> {
+    vec![
+        NativeFunction::new("clock", 0, clock),
+        NativeFunction::new("input", 0, input),
+        NativeFunction::new("len", 1, len),
+        NativeFunction::new("str", 1, str),
+        NativeFunction::new("num", 1, num),
+        NativeFunction::new("type", 1, type_of),
+    ]
+}
+
+//Seconds since the epoch, with decimals. Useful to benchmark scripts
+fn clock(_: &mut Interpreter, _: Vec<Object>) -> NativeResult {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_err(|e| RuntimeError::TypeError(e.to_string()))?;
+    Ok(Object::NumberObj(now.as_secs_f64()))
+}
+
+//Reads a line from the interpreter input, without the line break.
+//Returns nil once the input has ended
+fn input(interpreter: &mut Interpreter, _: Vec<Object>) -> NativeResult {
+    match interpreter.read_line()? {
+        Some(line) => Ok(Object::StringObj(line)),
+        None => Ok(Object::NullObj),
+    }
+}
+
+fn len(_: &mut Interpreter, arguments: Vec<Object>) -> NativeResult {
+    match &arguments[0] {
+        Object::StringObj(s) => Ok(Object::NumberObj(s.chars().count() as f64)),
+        o => Err(RuntimeError::TypeError(format!(
+            "len() expects a string, got {:?}",
+            o
+        ))),
+    }
+}
+
+fn str(_: &mut Interpreter, arguments: Vec<Object>) -> NativeResult {
+    Ok(Object::StringObj(arguments[0].to_string()))
+}
+
+fn num(_: &mut Interpreter, arguments: Vec<Object>) -> NativeResult {
+    match &arguments[0] {
+        Object::NumberObj(n) => Ok(Object::NumberObj(*n)),
+        Object::StringObj(s) => match s.trim().parse::<f64>() {
+            Ok(n) => Ok(Object::NumberObj(n)),
+            Err(_) => Err(RuntimeError::TypeError(format!(
+                "num() can not convert {:?} into a number",
+                s
+            ))),
+        },
+        o => Err(RuntimeError::TypeError(format!(
+            "num() expects a string or a number, got {:?}",
+            o
+        ))),
+    }
+}
+
+fn type_of(_: &mut Interpreter, arguments: Vec<Object>) -> NativeResult {
+    let name = match &arguments[0] {
+        Object::StringObj(_) => "string",
+        Object::BoolObj(_) => "boolean",
+        Object::NumberObj(_) => "number",
+        Object::FunctionObj(_) | Object::NativeFunctionObj(_) => "function",
+        Object::ClassObj(_) => "class",
+        Object::InstanceObj(_) => "instance",
+        Object::NullObj => "nil",
+    };
+    Ok(Object::StringObj(name.to_string()))
+}
+
+#[cfg(test)]
+mod native_tests {
+    use crate::{enviroment::Environment, object::Object, tests::helpers::run_in};
+
+    use super::builtins;
+
+    fn run(src: &str) -> Environment {
+        let mut enviroment = Environment::new();
+        for native in builtins() {
+            native.define_in(&mut enviroment).unwrap();
+        }
+        _ = run_in(src, &enviroment);
+        enviroment
+    }
+
+    #[test]
+    fn clock_returns_number() {
+        let env = run("var x = clock();");
+        assert!(matches!(
+            env.get(&"x".to_string()),
+            Some(Object::NumberObj(_))
+        ));
+    }
+
+    #[test]
+    fn len_str_num() {
+        let env = run("var x = len(\"hola\"); var y = str(3) + \"!\"; var z = num(\" 2.5 \");");
+        assert_eq!(env.get(&"x".to_string()), Some(Object::NumberObj(4.0)));
+        assert_eq!(
+            env.get(&"y".to_string()),
+            Some(Object::StringObj("3!".to_string()))
+        );
+        assert_eq!(env.get(&"z".to_string()), Some(Object::NumberObj(2.5)));
+    }
+
+    #[test]
+    fn type_names() {
+        let env = run("fun f() {} var a = type(1); var b = type(f()); var c = type(clock); class A {} var d = type(A());");
+        assert_eq!(
+            env.get(&"a".to_string()),
+            Some(Object::StringObj("number".to_string()))
+        );
+        assert_eq!(
+            env.get(&"b".to_string()),
+            Some(Object::StringObj("nil".to_string()))
+        );
+        assert_eq!(
+            env.get(&"c".to_string()),
+            Some(Object::StringObj("function".to_string()))
+        );
+        assert_eq!(
+            env.get(&"d".to_string()),
+            Some(Object::StringObj("instance".to_string()))
+        );
+    }
+
+    #[test]
+    fn invalid_num_fails() {
+        let env = run("var x = num(\"abc\");");
+        assert_eq!(env.get(&"x".to_string()), None);
+    }
+
+    #[test]
+    fn native_arity_is_checked() {
+        let env = run("var x = len(\"a\", \"b\");");
+        assert_eq!(env.get(&"x".to_string()), None);
+    }
+}
@@ -1,7 +1,12 @@
 use std::fmt::Display;
 use std::ops;
 
+use std::rc::Rc;
+
+use crate::callable::Function;
+use crate::class::{Class, Instance};
 use crate::interpreter::RuntimeError;
+use crate::native::NativeFunction;
 /*All this object abstraction is a workaround for the difficulties of
 doing runtime checking of types in Rust
 Is quite difficult in rust to return Any from a function and to cast on runtime
@@ -14,19 +19,37 @@ pub enum Object {
     StringObj(String),
     BoolObj(bool),
     NumberObj(f64),
+    FunctionObj(Function),
+    NativeFunctionObj(NativeFunction),
+    ClassObj(Rc<Class>),
+    InstanceObj(Instance),
     NullObj,
 }
 
+//How the values behave as conditions and how nil is printed. Spec follows
+//the Lox book, the permissive one is the behaviour rulox had before following it
+#[derive(Debug, Default, PartialEq, Clone, Copy)]
+pub enum Semantics {
+    //Only nil and false are falsey, anything else is truthy
+    #[default]
+    Spec,
+    //0 and "" are falsey too. Any value that is not a boolean, a number
+    //or a string can not be used as a condition. nil is printed as Nil
+    Permissive,
+}
+
 impl Object {
-    pub fn is_truthy(&self) -> bool {
-        match self {
-            Object::BoolObj(v) => *v,
-            Object::NumberObj(n) => *n != 0.0,
-            Object::StringObj(s) => !s.is_empty(),
-            _ => unimplemented!(
+    pub fn is_truthy(&self, semantics: Semantics) -> Result<bool, RuntimeError> {
+        match (self, semantics) {
+            (Object::BoolObj(v), _) => Ok(*v),
+            (Object::NullObj, Semantics::Spec) => Ok(false),
+            (_, Semantics::Spec) => Ok(true),
+            (Object::NumberObj(n), Semantics::Permissive) => Ok(*n != 0.0),
+            (Object::StringObj(s), Semantics::Permissive) => Ok(!s.is_empty()),
+            (_, Semantics::Permissive) => Err(RuntimeError::TypeError(format!(
                 "Can not use objects of type {:?} as boolean expression.",
                 self
-            ),
+            ))),
         }
     }
 }
@@ -35,9 +58,84 @@ impl Display for Object {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
             Object::BoolObj(v) => f.write_fmt(format_args!("{}", v.to_string())),
+            //Integers are shown without decimals, ie. 3 and not 3.0
             Object::NumberObj(v) => f.write_fmt(format_args!("{}", v)),
             Object::StringObj(v) => f.write_fmt(format_args!("{}", v)),
-            Object::NullObj => f.write_str("Nil"),
+            Object::FunctionObj(v) => f.write_fmt(format_args!("{}", v)),
+            Object::NativeFunctionObj(v) => f.write_fmt(format_args!("{}", v)),
+            Object::ClassObj(v) => f.write_fmt(format_args!("{}", v)),
+            Object::InstanceObj(v) => f.write_fmt(format_args!("{}", v)),
+            Object::NullObj => f.write_str("nil"),
+        }
+    }
+}
+
+//Conversions used by the hosts to move values in and out of the scripts
+impl From<f64> for Object {
+    fn from(value: f64) -> Self {
+        Object::NumberObj(value)
+    }
+}
+
+impl From<bool> for Object {
+    fn from(value: bool) -> Self {
+        Object::BoolObj(value)
+    }
+}
+
+impl From<String> for Object {
+    fn from(value: String) -> Self {
+        Object::StringObj(value)
+    }
+}
+
+impl From<&str> for Object {
+    fn from(value: &str) -> Self {
+        Object::StringObj(value.to_string())
+    }
+}
+
+impl From<()> for Object {
+    fn from(_: ()) -> Self {
+        Object::NullObj
+    }
+}
+
+impl TryFrom<Object> for f64 {
+    type Error = RuntimeError;
+    fn try_from(value: Object) -> Result<Self, Self::Error> {
+        match value {
+            Object::NumberObj(n) => Ok(n),
+            _ => Err(RuntimeError::TypeError(format!(
+                "Expected a number, got {:?}",
+                value
+            ))),
+        }
+    }
+}
+
+impl TryFrom<Object> for bool {
+    type Error = RuntimeError;
+    fn try_from(value: Object) -> Result<Self, Self::Error> {
+        match value {
+            Object::BoolObj(b) => Ok(b),
+            _ => Err(RuntimeError::TypeError(format!(
+                "Expected a boolean, got {:?}",
+                value
+            ))),
+        }
+    }
+}
+
+impl TryFrom<Object> for String {
+    type Error = RuntimeError;
+    fn try_from(value: Object) -> Result<Self, Self::Error> {
+        match value {
+            Object::StringObj(s) => Ok(s),
+            _ => Err(RuntimeError::TypeError(format!(
+                "Expected a string, got {:?}",
+                value
+            ))),
         }
     }
 }
@@ -182,6 +280,7 @@ impl Object {
     impl_cmp!(lt, <);
     impl_cmp!(lte, <=);
 
+    //Values of different types are never equal, ie. 1 == "1" is false
     pub fn eq(&self, other: &Self) -> Result<Object, RuntimeError> {
         Ok(Object::BoolObj(self == other))
     }
@@ -0,0 +1,601 @@
+use std::{
+    collections::{HashMap, HashSet},
+    fmt::Display,
+    rc::Rc,
+};
+
+use crate::{
+    ast::{
+        expr::{Depth, Expr, ExprKind},
+        span::Span,
+        stmt::{FunctionDecl, Stmt, StmtKind},
+        token::Token,
+    },
+    callable::THIS,
+    class::{INITIALIZER, SUPER},
+    stack::StackGuard,
+};
+
+#[derive(Debug, PartialEq)]
+pub enum ResolverError {
+    ReadInOwnInitializer(String, Span),
+    AlreadyDeclared(String, Span),
+    ReturnOutsideFunction(Span),
+    ReturnFromInitializer(Span),
+    ThisOutsideClass(Span),
+    InheritFromItself(String, Span),
+    SuperOutsideClass(Span),
+    SuperWithoutSuperclass(Span),
+    AssignToConst(String, Span),
+    TooDeeplyNested(Span),
+}
+
+impl ResolverError {
+    //The description alone, without the error kind prefix
+    pub fn message(&self) -> String {
+        match self {
+            Self::ReadInOwnInitializer(name, _) => {
+                format!("Can't read local variable {} in its own initializer", name)
+            }
+            Self::AlreadyDeclared(name, _) => {
+                format!("Already a variable named {} in this scope", name)
+            }
+            Self::ReturnFromInitializer(_) => {
+                String::from("Can't return a value from an initializer")
+            }
+            Self::ThisOutsideClass(_) => String::from("Can't use 'this' outside of a class"),
+            Self::InheritFromItself(name, _) => format!("Class {} can't inherit from itself", name),
+            Self::SuperOutsideClass(_) => String::from("Can't use 'super' outside of a class"),
+            Self::SuperWithoutSuperclass(_) => {
+                String::from("Can't use 'super' in a class with no superclass")
+            }
+            Self::ReturnOutsideFunction(_) => String::from("Can't return from top-level code"),
+            Self::AssignToConst(name, _) => format!("Can't assign to the constant {}", name),
+            Self::TooDeeplyNested(_) => String::from("Nested too deeply to fit in the stack"),
+        }
+    }
+
+    //The name or keyword that caused the error
+    pub fn span(&self) -> Span {
+        match self {
+            Self::ReadInOwnInitializer(_, span)
+            | Self::AlreadyDeclared(_, span)
+            | Self::ReturnOutsideFunction(span)
+            | Self::ReturnFromInitializer(span)
+            | Self::ThisOutsideClass(span)
+            | Self::InheritFromItself(_, span)
+            | Self::SuperOutsideClass(span)
+            | Self::SuperWithoutSuperclass(span)
+            | Self::AssignToConst(_, span)
+            | Self::TooDeeplyNested(span) => *span,
+        }
+    }
+}
+
+impl Display for ResolverError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "[Error] - Resolution error: {}", self.message())
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum FunctionType {
+    None,
+    Function,
+    Method,
+    Initializer,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum ClassType {
+    None,
+    Class,
+    Subclass,
+}
+
+//Static pass that runs between the parser and the interpreter.
+//It walks the whole AST once and writes, on every variable use, how many
+//scopes away is the declaration. The interpreter uses that depth to go
+//straight to the right scope, instead of searching by name at runtime.
+//The global scope is not tracked, anything not found is assumed to be global
+pub struct Resolver {
+    //Every local scope maps the variable name to "is it already initialized?"
+    scopes: Vec<HashMap<String, bool>>,
+    //Names declared with const in every scope. The first one is the global
+    //scope, the rest go along with the ones in scopes
+    consts: Vec<HashSet<String>>,
+    current_function: FunctionType,
+    current_class: ClassType,
+    errors: Vec<ResolverError>,
+    stack: StackGuard,
+}
+
+type ResolverResult = Result<(), Vec<ResolverError>>;
+
+impl Default for Resolver {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Resolver {
+    pub fn new() -> Self {
+        Self {
+            scopes: Vec::new(),
+            consts: vec![HashSet::new()],
+            current_function: FunctionType::None,
+            current_class: ClassType::None,
+            errors: Vec::new(),
+            stack: StackGuard::default(),
+        }
+    }
+
+    //Size of the stack of the thread that runs the resolver. The deepest
+    //nesting allowed depends on it
+    pub fn set_stack_size(&mut self, stack_size: usize) {
+        self.stack = StackGuard::new(stack_size);
+    }
+
+    //Resolves every statement, even after an error, so all of them are reported at once
+    pub fn resolve(&mut self, stmts: &[Stmt]) -> ResolverResult {
+        self.stack.start();
+        for s in stmts.iter() {
+            self.resolve_stmt(s);
+        }
+
+        if self.errors.is_empty() {
+            return Ok(());
+        }
+        Err(std::mem::take(&mut self.errors))
+    }
+
+    fn resolve_stmt(&mut self, stmt: &Stmt) {
+        if !self.stack.has_room() {
+            return self.too_deep(stmt.span);
+        }
+        match &stmt.kind {
+            StmtKind::EXPR(e) | StmtKind::PRINT(e) => self.resolve_expr(e),
+            StmtKind::VAR(name, _, initializer) => {
+                //Declared but not defined yet, so var a = a; can be detected
+                self.declare(name);
+                if let Some(e) = initializer {
+                    self.resolve_expr(e);
+                }
+                self.define(&name.lexeme);
+            }
+            StmtKind::CONST(name, _, value) => {
+                self.declare(name);
+                self.resolve_expr(value);
+                self.define(&name.lexeme);
+                if let Some(consts) = self.consts.last_mut() {
+                    consts.insert(name.lexeme.clone());
+                }
+            }
+            StmtKind::BLOCK(stmts) => {
+                self.begin_scope();
+                self.resolve_block(stmts);
+                self.end_scope();
+            }
+            StmtKind::IF(condition, then, else_) => {
+                self.resolve_expr(condition);
+                self.resolve_stmt(then);
+                if let Some(e) = else_ {
+                    self.resolve_stmt(e);
+                }
+            }
+            StmtKind::WHILE(condition, body) => {
+                self.resolve_expr(condition);
+                self.resolve_stmt(body);
+            }
+            StmtKind::FUNCTION(declaration) => {
+                //The name is defined before the body, so the function can call itself
+                self.declare(&declaration.name);
+                self.define(&declaration.name.lexeme);
+                self.resolve_function(declaration, FunctionType::Function);
+            }
+            StmtKind::RETURN(keyword, value) => {
+                if self.current_function == FunctionType::None {
+                    self.errors
+                        .push(ResolverError::ReturnOutsideFunction(keyword.span));
+                }
+                if let Some(v) = value {
+                    if self.current_function == FunctionType::Initializer {
+                        self.errors
+                            .push(ResolverError::ReturnFromInitializer(keyword.span));
+                    }
+                    self.resolve_expr(v);
+                }
+            }
+            StmtKind::CLASS(name, superclass, methods) => {
+                self.resolve_class(name, superclass, methods)
+            }
+        }
+    }
+
+    //Methods are resolved inside an extra scope that only holds `this`.
+    //It matches the scope created at runtime when a method is bound.
+    //Subclasses have one more scope above it, holding `super`
+    fn resolve_class(
+        &mut self,
+        name: &Token,
+        superclass: &Option<Box<Expr>>,
+        methods: &[Rc<FunctionDecl>],
+    ) {
+        let enclosing_class = self.current_class;
+        self.current_class = ClassType::Class;
+
+        self.declare(name);
+        self.define(&name.lexeme);
+
+        if let Some(s) = superclass {
+            if let ExprKind::Variable(superclass_name, _) = &s.kind {
+                if **superclass_name == name.lexeme {
+                    self.errors.push(ResolverError::InheritFromItself(
+                        name.lexeme.clone(),
+                        s.span,
+                    ));
+                }
+            }
+            self.current_class = ClassType::Subclass;
+            self.resolve_expr(s);
+
+            self.begin_scope();
+            self.define(SUPER);
+        }
+
+        self.begin_scope();
+        self.define(THIS);
+        for method in methods.iter() {
+            let function_type = match method.name.lexeme == INITIALIZER {
+                true => FunctionType::Initializer,
+                false => FunctionType::Method,
+            };
+            self.resolve_function(method, function_type);
+        }
+        self.end_scope();
+
+        if superclass.is_some() {
+            self.end_scope();
+        }
+
+        self.current_class = enclosing_class;
+    }
+
+    fn resolve_block(&mut self, stmts: &[Box<Stmt>]) {
+        for s in stmts.iter() {
+            self.resolve_stmt(s);
+        }
+    }
+
+    //Parameters and body share the same scope, the same way the
+    //interpreter executes the body directly in the scope of the call
+    fn resolve_function(&mut self, declaration: &FunctionDecl, function_type: FunctionType) {
+        let enclosing_function = self.current_function;
+        self.current_function = function_type;
+
+        self.begin_scope();
+        for param in declaration.params.iter() {
+            self.declare(param);
+            self.define(&param.lexeme);
+        }
+        self.resolve_block(&declaration.body);
+        self.end_scope();
+
+        self.current_function = enclosing_function;
+    }
+
+    fn resolve_expr(&mut self, expr: &Expr) {
+        if !self.stack.has_room() {
+            return self.too_deep(expr.span);
+        }
+        match &expr.kind {
+            ExprKind::Variable(name, depth) => {
+                if let Some(scope) = self.scopes.last() {
+                    if scope.get(name.as_str()) == Some(&false) {
+                        self.errors.push(ResolverError::ReadInOwnInitializer(
+                            name.to_string(),
+                            expr.span,
+                        ));
+                    }
+                }
+                self.resolve_local(name, depth);
+            }
+            ExprKind::Assignment(name, value, depth)
+            | ExprKind::CompoundAssignment(name, _, value, depth) => {
+                self.check_not_const(name, expr.span);
+                self.resolve_expr(value);
+                self.resolve_local(name, depth);
+            }
+            ExprKind::Binary(left, _, right) | ExprKind::Logical(left, _, right) => {
+                self.resolve_expr(left);
+                self.resolve_expr(right);
+            }
+            ExprKind::Grouping(e) | ExprKind::Unary(_, e) => self.resolve_expr(e),
+            ExprKind::Conditional(condition, then, else_) => {
+                self.resolve_expr(condition);
+                self.resolve_expr(then);
+                self.resolve_expr(else_);
+            }
+            ExprKind::Call(callee, _, arguments) => {
+                self.resolve_expr(callee);
+                for argument in arguments.iter() {
+                    self.resolve_expr(argument);
+                }
+            }
+            ExprKind::Get(object, _) => self.resolve_expr(object),
+            ExprKind::Set(object, _, value) | ExprKind::CompoundSet(object, _, _, value) => {
+                self.resolve_expr(value);
+                self.resolve_expr(object);
+            }
+            ExprKind::This(keyword, depth) => {
+                if self.current_class == ClassType::None {
+                    self.errors
+                        .push(ResolverError::ThisOutsideClass(keyword.span));
+                    return;
+                }
+                self.resolve_local(&THIS.to_string(), depth);
+            }
+            ExprKind::Super(keyword, _, depth) => {
+                match self.current_class {
+                    ClassType::None => self
+                        .errors
+                        .push(ResolverError::SuperOutsideClass(keyword.span)),
+                    ClassType::Class => self
+                        .errors
+                        .push(ResolverError::SuperWithoutSuperclass(keyword.span)),
+                    ClassType::Subclass => {}
+                }
+                self.resolve_local(&SUPER.to_string(), depth);
+            }
+            ExprKind::NumberLit(_)
+            | ExprKind::StringLit(_)
+            | ExprKind::Boolean(_)
+            | ExprKind::Nil => {}
+        }
+    }
+
+    //Looks for the variable from the innermost scope to the outermost one.
+    //If it is not found the depth stays as None, and it will be treated as global
+    fn resolve_local(&mut self, name: &String, depth: &Depth) {
+        for (distance, scope) in self.scopes.iter().rev().enumerate() {
+            if scope.contains_key(name) {
+                depth.set(Some(distance));
+                return;
+            }
+        }
+        depth.set(None);
+    }
+
+    //Only the consts declared in the code being resolved are known. A global
+    //one from a previous input of the prompt is caught at runtime
+    fn check_not_const(&mut self, name: &String, assignment: Span) {
+        let scope = match self.scopes.iter().rposition(|s| s.contains_key(name)) {
+            Some(local) => local + 1,
+            None => 0,
+        };
+        if self.consts.get(scope).is_some_and(|c| c.contains(name)) {
+            //The error points at the name, the first thing of the assignment
+            let target = Span {
+                end: assignment.start + name.len(),
+                ..assignment
+            };
+            self.errors
+                .push(ResolverError::AssignToConst(name.clone(), target));
+        }
+    }
+
+    //Reported once, every node past that depth fails the same way
+    fn too_deep(&mut self, span: Span) {
+        let reported = self
+            .errors
+            .iter()
+            .any(|e| matches!(e, ResolverError::TooDeeplyNested(_)));
+        if !reported {
+            self.errors.push(ResolverError::TooDeeplyNested(span));
+        }
+    }
+
+    fn begin_scope(&mut self) {
+        self.scopes.push(HashMap::new());
+        self.consts.push(HashSet::new());
+    }
+
+    fn end_scope(&mut self) {
+        self.scopes.pop();
+        self.consts.pop();
+    }
+
+    fn declare(&mut self, name: &Token) {
+        //A new declaration replaces the const, ie. a global var with the same name
+        if let Some(consts) = self.consts.last_mut() {
+            consts.remove(&name.lexeme);
+        }
+        let Some(scope) = self.scopes.last_mut() else {
+            //Globals can be redeclared freely
+            return;
+        };
+        if scope.contains_key(&name.lexeme) {
+            self.errors.push(ResolverError::AlreadyDeclared(
+                name.lexeme.clone(),
+                name.span,
+            ));
+        }
+        scope.insert(name.lexeme.clone(), false);
+    }
+
+    fn define(&mut self, name: &str) {
+        if let Some(scope) = self.scopes.last_mut() {
+            scope.insert(name.to_string(), true);
+        }
+    }
+}
+
+#[cfg(test)]
+mod resolver_tests {
+    use crate::{
+        ast::{
+            expr::ExprKind,
+            parser::Parser,
+            scanner::Scanner,
+            span::Span,
+            stmt::{Stmt, StmtKind},
+        },
+        lox::Lox,
+    };
+
+    use super::{Resolver, ResolverError};
+
+    fn resolve(src: &str) -> (Vec<Stmt>, Result<(), Vec<ResolverError>>) {
+        let src = String::from(src);
+        let mut lox = Lox::default();
+        let mut scanner = Scanner::new(&mut lox, &src);
+        let tokens = scanner.scan_tokens();
+        let stmts = Parser::new(tokens).parse().unwrap();
+        let res = Resolver::new().resolve(&stmts);
+        (stmts, res)
+    }
+
+    #[test]
+    fn local_depth() {
+        let (stmts, res) = resolve("{ var a = 1; { print a; } }");
+        assert!(res.is_ok());
+        //BLOCK -> BLOCK -> PRINT(a)
+        let StmtKind::BLOCK(outer) = &stmts[0].kind else {
+            panic!("Expected a block")
+        };
+        let StmtKind::BLOCK(inner) = &outer[1].kind else {
+            panic!("Expected a block")
+        };
+        let StmtKind::PRINT(e) = &inner[0].kind else {
+            panic!("Expected a print")
+        };
+        match &e.kind {
+            ExprKind::Variable(_, depth) => assert_eq!(depth.get(), Some(1)),
+            e => panic!("Expected a variable, got {:?}", e),
+        }
+    }
+
+    #[test]
+    fn global_has_no_depth() {
+        let (stmts, res) = resolve("var a = 1; print a;");
+        assert!(res.is_ok());
+        let StmtKind::PRINT(e) = &stmts[1].kind else {
+            panic!("Expected a print")
+        };
+        match &e.kind {
+            ExprKind::Variable(_, depth) => assert_eq!(depth.get(), None),
+            e => panic!("Expected a variable, got {:?}", e),
+        }
+    }
+
+    #[test]
+    fn read_in_own_initializer() {
+        let (_, res) = resolve("var a = 1; { var a = a; }");
+        assert_eq!(
+            res,
+            Err(vec![ResolverError::ReadInOwnInitializer(
+                "a".to_string(),
+                Span::new(0, 21, 22, 1, 22)
+            )])
+        );
+    }
+
+    #[test]
+    fn redeclare_local() {
+        let (_, res) = resolve("fun f() { var a = 1; var a = 2; }");
+        assert_eq!(
+            res,
+            Err(vec![ResolverError::AlreadyDeclared(
+                "a".to_string(),
+                Span::new(0, 25, 26, 1, 26)
+            )])
+        );
+    }
+
+    #[test]
+    fn redeclare_global_is_allowed() {
+        let (_, res) = resolve("var a = 1; var a = 2;");
+        assert!(res.is_ok());
+    }
+
+    #[test]
+    fn this_outside_class() {
+        let (_, res) = resolve("fun f() { return this; }");
+        assert_eq!(
+            res,
+            Err(vec![ResolverError::ThisOutsideClass(Span::new(
+                0, 17, 21, 1, 18
+            ))])
+        );
+    }
+
+    #[test]
+    fn return_value_from_initializer() {
+        let (_, res) = resolve("class A { init() { return 1; } }");
+        assert_eq!(
+            res,
+            Err(vec![ResolverError::ReturnFromInitializer(Span::new(
+                0, 19, 25, 1, 20
+            ))])
+        );
+    }
+
+    #[test]
+    fn inherit_from_itself() {
+        let (_, res) = resolve("class A < A {}");
+        assert_eq!(
+            res,
+            Err(vec![ResolverError::InheritFromItself(
+                "A".to_string(),
+                Span::new(0, 10, 11, 1, 11)
+            )])
+        );
+    }
+
+    #[test]
+    fn super_without_superclass() {
+        let (_, res) = resolve("class A { f() { return super.f(); } }");
+        assert_eq!(
+            res,
+            Err(vec![ResolverError::SuperWithoutSuperclass(Span::new(
+                0, 23, 28, 1, 24
+            ))])
+        );
+    }
+
+    #[test]
+    fn super_outside_class() {
+        let (_, res) = resolve("fun f() { return super.f(); }");
+        assert_eq!(
+            res,
+            Err(vec![ResolverError::SuperOutsideClass(Span::new(
+                0, 17, 22, 1, 18
+            ))])
+        );
+    }
+
+    #[test]
+    fn top_level_return() {
+        let (_, res) = resolve("return 1;");
+        assert_eq!(
+            res,
+            Err(vec![ResolverError::ReturnOutsideFunction(Span::new(
+                0, 0, 6, 1, 1
+            ))])
+        );
+    }
+
+    #[test]
+    fn assign_to_const() {
+        let a = ResolverError::AssignToConst("a".to_string(), Span::new(0, 13, 14, 1, 14));
+        assert_eq!(resolve("const a = 1; a = 2;").1, Err(vec![a]));
+        let a = ResolverError::AssignToConst("a".to_string(), Span::new(0, 17, 18, 1, 18));
+        assert_eq!(resolve("{ const a = 1; { a += 2; } }").1, Err(vec![a]));
+        let a = ResolverError::AssignToConst("a".to_string(), Span::new(0, 23, 24, 1, 24));
+        assert_eq!(resolve("const a = 1; fun f() { a = 2; }").1, Err(vec![a]));
+
+        //Shadowed or declared again with var, it can be assigned
+        assert!(resolve("const a = 1; { var a = 2; a = 3; }").1.is_ok());
+        assert!(resolve("const a = 1; var a = 2; a = 3;").1.is_ok());
+    }
+}
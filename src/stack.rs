@@ -0,0 +1,59 @@
+//The parser, the resolver, the type checker and the interpreter walk the
+//tree recursively, and the tree can be as deep as the code is long (ie. a
+//chain of 5000 + is 5000 levels deep). So the depth is limited by the
+//bytes of host stack a pass uses and not by a count of levels.
+//A level takes from a few hundred bytes (the resolver) to 10KB (a call
+//in a debug build). 2MB is what Rust gives to the threads it spawns (the
+//tests ones too)
+pub const DEFAULT_STACK_SIZE: usize = 2 * 1024 * 1024;
+
+//Left free when a level checks the stack, for the code that runs until
+//the next check (natives, building the error...)
+pub const STACK_RESERVE: usize = 512 * 1024;
+
+//Address of a local of the caller, to know how deep the host stack is
+#[inline(always)]
+fn stack_address() -> usize {
+    let marker = 0u8;
+    std::ptr::addr_of!(marker) as usize
+}
+
+//Tells a recursive pass when the next level would not fit in the stack
+#[derive(Debug, Clone, Copy)]
+pub struct StackGuard {
+    size: usize,
+    //Where the pass started. Until start is called, the first check sets it
+    base: Option<usize>,
+}
+
+impl Default for StackGuard {
+    fn default() -> Self {
+        Self::new(DEFAULT_STACK_SIZE)
+    }
+}
+
+impl StackGuard {
+    //The size is the one of the stack of the thread running the pass
+    pub fn new(size: usize) -> Self {
+        Self { size, base: None }
+    }
+
+    //The pass starts at the current depth of the host stack
+    #[inline(always)]
+    pub fn start(&mut self) {
+        self.base = Some(stack_address());
+    }
+
+    //Bytes of stack not used yet by the pass
+    #[inline(always)]
+    pub fn left(&mut self) -> usize {
+        let here = stack_address();
+        let base = *self.base.get_or_insert(here);
+        self.size.saturating_sub(base.abs_diff(here))
+    }
+
+    #[inline(always)]
+    pub fn has_room(&mut self) -> bool {
+        self.left() > STACK_RESERVE
+    }
+}
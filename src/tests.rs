@@ -1,10 +1,374 @@
+//Pipeline shared by the unit tests of the modules that need a program
+//already resolved, without the type checker and the rest of Lox
+#[cfg(test)]
+pub(crate) mod helpers {
+    use crate::{
+        ast::{parser::Parser, scanner::Scanner, stmt::Stmt},
+        enviroment::Environment,
+        interpreter::{Interpreter, RuntimeFailure},
+        lox::Lox,
+        object::Object,
+        resolver::Resolver,
+    };
+
+    //Scanned, parsed and resolved. The source must be valid
+    pub fn resolve_source(src: &str) -> Vec<Stmt> {
+        let mut lox = Lox::default();
+        let mut scanner = Scanner::new(&mut lox, src);
+        let stmts = Parser::new(scanner.scan_tokens()).parse().unwrap();
+        Resolver::new().resolve(&stmts).unwrap();
+        stmts
+    }
+
+    //Runs the program with the received scope as its globals
+    pub fn run_in(src: &str, globals: &Environment) -> Result<Object, RuntimeFailure> {
+        Interpreter::new(globals).interpret(&resolve_source(src))
+    }
+}
+
 #[cfg(test)]
 mod end_to_end_tests {
-    // use crate::lox::Lox;
-    // #[test]
-    // fn excecute_value_negation() {
-    //     let src = String::from("-3");
-    //     let mut lox: Lox = Lox::default();
-    //     lox.run(src);
-    // }
+    use std::{cell::RefCell, io::Cursor, rc::Rc};
+
+    use crate::{interpreter::RuntimeError, lox::Lox, lox::LoxError, object::Semantics};
+
+    //Runs the source in a fresh interpreter, with the streams captured.
+    //Rendering the error is part of the run, it must not panic either
+    fn run(source: &str) -> Result<(), LoxError> {
+        let mut lox = Lox::new();
+        lox.set_output(Rc::new(RefCell::new(Vec::<u8>::new())));
+        lox.set_input(Rc::new(RefCell::new(Cursor::new(""))));
+        let res = lox.eval(source);
+        if let Err(e) = &res {
+            _ = lox.render_error(e);
+        }
+        res.map(|_| ())
+    }
+
+    //Malformed programs that used to crash the interpreter
+    const MALFORMED: [&str; 31] = [
+        ";",
+        "print",
+        "print ;",
+        "1 +",
+        "1 + ;",
+        "-",
+        "!",
+        "(",
+        "((1)",
+        ")",
+        "}",
+        "{",
+        "{ var a = 1;",
+        "var",
+        "var a =",
+        "fun",
+        "fun f(",
+        "fun f() {",
+        "class",
+        "class A < {",
+        "return",
+        "if (true)",
+        "if (true) { } else",
+        "while (",
+        "for (;;",
+        "a.b.c = ",
+        "super.",
+        "\"unterminated",
+        "/* unterminated",
+        "/* a */ /",
+        "@#$ 1.",
+    ];
+
+    //Valid programs. Every prefix of them is a malformed program
+    const PROGRAMS: [&str; 4] = [
+        "var a = 1; { var b = a + 2; print b * 3; } if (a == 1) { print \"one\"; } else { print nil; }",
+        "fun add(a, b) { return a + b; } print add(1, 2); var f = add; print f(3, 4);",
+        "class A { init(x) { this.x = x; } get() { return this.x; } } class B < A { get() { return super.get() + 1; } } print B(1).get();",
+        "for (var i = 0; i < 3; i = i + 1) { print i; } /* done */ // end\nprint \"ok\";",
+    ];
+
+    #[test]
+    fn malformed_programs_do_not_panic() {
+        for source in MALFORMED {
+            assert!(run(source).is_err(), "{:?} should fail", source);
+        }
+    }
+
+    #[test]
+    fn every_prefix_runs_without_panicking() {
+        for program in PROGRAMS {
+            assert!(run(program).is_ok(), "{:?} should run", program);
+            for (end, _) in program.char_indices() {
+                _ = run(&program[..end]);
+            }
+        }
+    }
+
+    #[test]
+    fn permissive_conditions_fail_instead_of_panicking() {
+        for source in [
+            "if (nil) { print 1; }",
+            "fun f() {} while (f) { print 1; }",
+            "print nil and 1;",
+            "class A {} print A or 1;",
+        ] {
+            let mut lox = Lox::new();
+            lox.set_output(Rc::new(RefCell::new(Vec::<u8>::new())));
+            lox.set_semantics(Semantics::Permissive);
+            assert!(
+                matches!(lox.eval(source), Err(LoxError::Runtime(_))),
+                "{:?}",
+                source
+            );
+        }
+    }
+
+    #[test]
+    fn deep_recursion_is_a_runtime_error() {
+        let res = run("fun f(n) { if (true) { { return f(n + 1); } } } f(0);");
+        match res {
+            Err(LoxError::Runtime(failure)) => {
+                assert_eq!(failure.error, RuntimeError::StackOverflow)
+            }
+            r => panic!("Expected a stack overflow, got {:?}", r),
+        }
+    }
+
+    #[test]
+    fn deep_nesting_is_a_syntax_error() {
+        for source in [
+            format!("print {}1{};", "(".repeat(1000), ")".repeat(1000)),
+            format!("print {}1;", "-".repeat(1000)),
+            format!("{}{}", "{".repeat(1000), "}".repeat(1000)),
+            format!("var a; {}1;", "a = ".repeat(1000)),
+        ] {
+            assert!(matches!(run(&source), Err(LoxError::Parse(_))));
+        }
+    }
+
+    #[test]
+    fn deep_nesting_fits_in_a_bigger_stack() {
+        const STACK_SIZE: usize = 64 * 1024 * 1024;
+        let sources = [
+            (
+                format!("print {}1{};", "(".repeat(2000), ")".repeat(2000)),
+                "1",
+            ),
+            (format!("print 0{};", " + 1".repeat(5000)), "5000"),
+            (format!("print 1{};", " == true".repeat(5000)), "false"),
+            (
+                format!(
+                    "if (false) {{}}{} else print 2;",
+                    " else if (false) {}".repeat(2000)
+                ),
+                "2",
+            ),
+        ];
+        let runner = std::thread::Builder::new()
+            .stack_size(STACK_SIZE)
+            .spawn(move || {
+                for (source, expected) in sources {
+                    let output = Rc::new(RefCell::new(Vec::<u8>::new()));
+                    let mut lox = Lox::new();
+                    lox.set_output(output.clone());
+                    lox.set_stack_size(STACK_SIZE);
+                    assert!(lox.eval(&source).is_ok(), "{:.40}", source);
+                    let printed = String::from_utf8(output.take()).unwrap();
+                    assert_eq!(printed.trim_end(), expected);
+                }
+            })
+            .unwrap();
+        if let Err(panic) = runner.join() {
+            std::panic::resume_unwind(panic)
+        }
+    }
+
+    //Deterministic pseudo random numbers, so a failure can be reproduced
+    struct Lcg(u64);
+
+    impl Lcg {
+        fn next(&mut self, bound: usize) -> usize {
+            self.0 = self
+                .0
+                .wrapping_mul(6364136223846793005)
+                .wrapping_add(1442695040888963407);
+            ((self.0 >> 33) as usize) % bound
+        }
+    }
+
+    //Loops are left out, random conditions could run forever
+    const PIECES: [&str; 44] = [
+        "var", "a", "b", "f", "A", "=", "==", "!=", "<", ">=", "+", "-", "*", "/", "!", "and",
+        "or", "(", ")", "{", "}", ",", ".", ";", "1", "0", "\"s\"", "\"\"", "true", "false", "nil",
+        "print", "fun", "return", "class", "this", "super", "if", "else", "init", "<", "/*", "?",
+        ":",
+    ];
+
+    #[test]
+    fn random_token_soup_does_not_panic() {
+        let mut rng = Lcg(42);
+        for _ in 0..2000 {
+            let len = rng.next(30);
+            let source: Vec<&str> = (0..len).map(|_| PIECES[rng.next(PIECES.len())]).collect();
+            _ = run(&source.join(" "));
+        }
+    }
+
+    #[test]
+    fn random_characters_do_not_panic() {
+        let alphabet: Vec<char> = "ab1.;(){}=+-*/!<>\"\n\t /*é€\0".chars().collect();
+        let mut rng = Lcg(7);
+        for _ in 0..2000 {
+            let len = rng.next(40);
+            let source: String = (0..len)
+                .map(|_| alphabet[rng.next(alphabet.len())])
+                .collect();
+            _ = run(&source);
+        }
+    }
+}
+
+//Runs every tests/**/*.lox script and checks it against the comments in it,
+//like the suite of the Crafting Interpreters book:
+//
+//  print 1 + 2; // expect: 3
+//  print a;     // expect runtime error: Undefined variable a
+//  // [line 3] Error: Unexpected token - Expected expression, got SEMICOLON
+//
+//Compile errors are written with the line they are reported at
+#[cfg(test)]
+mod conformance_tests {
+    use std::{
+        cell::RefCell,
+        fs,
+        path::{Path, PathBuf},
+        rc::Rc,
+    };
+
+    use crate::lox::{Lox, LoxError};
+
+    const EXPECT: &str = "// expect: ";
+    const EXPECT_RUNTIME_ERROR: &str = "// expect runtime error: ";
+
+    //What a script must do, taken from its comments
+    #[derive(Default)]
+    struct Expectations {
+        output: Vec<String>,
+        compile_errors: Vec<String>,
+        //The message and the line of the comment
+        runtime_error: Option<(String, usize)>,
+    }
+
+    impl Expectations {
+        fn parse(source: &str) -> Self {
+            let mut expectations = Self::default();
+            for (i, line) in source.lines().enumerate() {
+                let line_number = i + 1;
+                if let Some(pos) = line.find(EXPECT) {
+                    let output = &line[pos + EXPECT.len()..];
+                    expectations.output.push(output.to_string());
+                } else if let Some(pos) = line.find(EXPECT_RUNTIME_ERROR) {
+                    let message = &line[pos + EXPECT_RUNTIME_ERROR.len()..];
+                    expectations.runtime_error = Some((message.to_string(), line_number));
+                } else if let Some(pos) = line.find("// [line ") {
+                    expectations
+                        .compile_errors
+                        .push(line[pos + 3..].to_string());
+                }
+            }
+            expectations
+        }
+    }
+
+    fn lox_files(dir: &Path, files: &mut Vec<PathBuf>) {
+        let Ok(entries) = fs::read_dir(dir) else {
+            return;
+        };
+        for entry in entries.flatten() {
+            let path = entry.path();
+            if path.is_dir() {
+                lox_files(&path, files);
+            } else if path.extension().is_some_and(|e| e == "lox") {
+                files.push(path);
+            }
+        }
+    }
+
+    //Every difference between the expectations and what the script did
+    fn check(path: &Path) -> Vec<String> {
+        let source = fs::read_to_string(path).unwrap();
+        let expected = Expectations::parse(&source);
+
+        let output = Rc::new(RefCell::new(Vec::<u8>::new()));
+        let mut lox = Lox::new();
+        lox.set_output(output.clone());
+        lox.set_quiet(true);
+        let res = lox.run_file(&path.to_string_lossy().to_string());
+
+        let mut problems = Vec::new();
+        let printed = String::from_utf8_lossy(&output.borrow()).to_string();
+        let printed: Vec<&str> = printed.lines().collect();
+        if printed != expected.output {
+            problems.push(format!(
+                "expected output {:?}, got {:?}",
+                expected.output, printed
+            ));
+        }
+
+        let mut compile_errors = Vec::new();
+        let mut runtime_error = None;
+        match &res {
+            Ok(()) => {}
+            Err(LoxError::Runtime(failure)) => {
+                let line = failure.span.map(|s| s.line).unwrap_or(0);
+                runtime_error = Some((failure.error.message(), line));
+            }
+            Err(e) => {
+                for diagnostic in e.diagnostics() {
+                    let line = diagnostic.span.map(|s| s.line).unwrap_or(0);
+                    compile_errors.push(format!("[line {}] Error: {}", line, diagnostic.message));
+                }
+            }
+        }
+
+        if compile_errors != expected.compile_errors {
+            problems.push(format!(
+                "expected compile errors {:?}, got {:?}",
+                expected.compile_errors, compile_errors
+            ));
+        }
+        if runtime_error != expected.runtime_error {
+            problems.push(format!(
+                "expected runtime error {:?}, got {:?}",
+                expected.runtime_error, runtime_error
+            ));
+        }
+        problems
+    }
+
+    #[test]
+    fn lox_scripts() {
+        let mut files = Vec::new();
+        lox_files(
+            &Path::new(env!("CARGO_MANIFEST_DIR")).join("tests"),
+            &mut files,
+        );
+        files.sort();
+        assert!(!files.is_empty(), "No .lox files found in tests/");
+
+        let mut failures = Vec::new();
+        for file in files.iter() {
+            for problem in check(file) {
+                failures.push(format!("{}: {}", file.display(), problem));
+            }
+        }
+        assert!(
+            failures.is_empty(),
+            "{} problems in {} scripts:\n{}",
+            failures.len(),
+            files.len(),
+            failures.join("\n")
+        );
+    }
 }
@@ -0,0 +1,636 @@
+use std::{collections::HashMap, fmt::Display, rc::Rc};
+
+use crate::{
+    ast::{
+        expr::{Expr, ExprKind},
+        span::Span,
+        stmt::{FunctionDecl, Stmt, StmtKind},
+        token::{Token, TokenType},
+    },
+    stack::StackGuard,
+};
+
+//What the checker knows about a value. Any is a value it knows nothing
+//about: the variables without a type, fields, calls to untyped functions...
+//Any operation on it is allowed, and left for the interpreter to check
+#[derive(Debug, Clone, PartialEq)]
+pub enum Type {
+    Any,
+    Nil,
+    Boolean,
+    Number,
+    String,
+    Function(Rc<Signature>),
+}
+
+//The types of a function declared with fun. Untyped params and returns are Any
+#[derive(Debug, PartialEq)]
+pub struct Signature {
+    params: Vec<Type>,
+    ret: Type,
+}
+
+impl Type {
+    //The names used in the annotations, ie. var a: number
+    fn from_name(name: &str) -> Option<Type> {
+        match name {
+            "any" => Some(Type::Any),
+            "nil" => Some(Type::Nil),
+            "boolean" => Some(Type::Boolean),
+            "number" => Some(Type::Number),
+            "string" => Some(Type::String),
+            _ => None,
+        }
+    }
+
+    //A value of the received type can be stored where this one is expected
+    fn accepts(&self, other: &Type) -> bool {
+        match (self, other) {
+            (Type::Any, _) | (_, Type::Any) => true,
+            (Type::Function(_), Type::Function(_)) => true,
+            _ => self == other,
+        }
+    }
+
+    //The type of a value that can be any of both, ie. the branches of a ?:
+    fn join(self, other: Type) -> Type {
+        match self == other {
+            true => self,
+            false => Type::Any,
+        }
+    }
+}
+
+impl Display for Type {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Type::Any => write!(f, "any"),
+            Type::Nil => write!(f, "nil"),
+            Type::Boolean => write!(f, "boolean"),
+            Type::Number => write!(f, "number"),
+            Type::String => write!(f, "string"),
+            Type::Function(_) => write!(f, "function"),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum TypeCheckError {
+    UnknownType(String, Span),
+    InvalidOperands(String, Span), //What the operator expects and what it got
+    Mismatch(String, Type, Type, Span), //What was checked, expected type, found type
+    Uninitialized(String, Type, Span), //Variable name, its type
+    TooDeeplyNested(Span),
+}
+
+impl TypeCheckError {
+    //The description alone, without the error kind prefix
+    pub fn message(&self) -> String {
+        match self {
+            Self::UnknownType(name, _) => format!("Unknown type {}", name),
+            Self::InvalidOperands(message, _) => message.clone(),
+            Self::Mismatch(target, expected, found, _) => format!(
+                "Type mismatch in {}: expected {}, got {}",
+                target, expected, found
+            ),
+            Self::Uninitialized(name, type_, _) => {
+                format!("Variable {} of type {} needs an initial value", name, type_)
+            }
+            Self::TooDeeplyNested(_) => String::from("Nested too deeply to fit in the stack"),
+        }
+    }
+
+    pub fn span(&self) -> Span {
+        match self {
+            Self::UnknownType(_, span)
+            | Self::InvalidOperands(_, span)
+            | Self::Mismatch(_, _, _, span)
+            | Self::Uninitialized(_, _, span)
+            | Self::TooDeeplyNested(span) => *span,
+        }
+    }
+}
+
+impl Display for TypeCheckError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "[line {}:{}] Error : {}",
+            self.span().line,
+            self.span().column,
+            self.message()
+        )
+    }
+}
+
+//Static pass that runs after the resolver. The types come from the literals
+//and from the annotations (var a: number, fun f(a: string): boolean).
+//A value is only reported when its type is known for sure, so the code
+//without annotations is checked by the interpreter as it always was.
+//It does not follow the control flow: a typed variable keeps its type for
+//its whole scope. The only exception is the end of a function, that is a
+//return of nil unless every path of the body has returned before
+#[derive(Default)]
+pub struct TypeChecker {
+    //Same scopes as the resolver, with the type of every variable
+    scopes: Vec<HashMap<String, Type>>,
+    globals: HashMap<String, Type>,
+    //Name and return type of the function being checked
+    current_function: Option<(String, Type)>,
+    errors: Vec<TypeCheckError>,
+    stack: StackGuard,
+}
+
+type TypeCheckResult = Result<(), Vec<TypeCheckError>>;
+
+impl TypeChecker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    //Starts with the globals of the code checked before, ie. the previous
+    //inputs of the prompt. into_globals gives them back with the new ones
+    pub fn with_globals(globals: HashMap<String, Type>) -> Self {
+        Self {
+            globals,
+            ..Self::default()
+        }
+    }
+
+    pub fn into_globals(self) -> HashMap<String, Type> {
+        self.globals
+    }
+
+    //Size of the stack of the thread that runs the checker. The deepest
+    //nesting allowed depends on it
+    pub fn set_stack_size(&mut self, stack_size: usize) {
+        self.stack = StackGuard::new(stack_size);
+    }
+
+    //Checks every statement, even after an error, so all of them are reported at once
+    pub fn check(&mut self, stmts: &[Stmt]) -> TypeCheckResult {
+        self.stack.start();
+        for stmt in stmts.iter() {
+            self.check_stmt(stmt);
+        }
+
+        if self.errors.is_empty() {
+            return Ok(());
+        }
+        Err(std::mem::take(&mut self.errors))
+    }
+
+    fn check_stmt(&mut self, stmt: &Stmt) {
+        if !self.stack.has_room() {
+            return self.too_deep(stmt.span);
+        }
+        match &stmt.kind {
+            StmtKind::EXPR(e) | StmtKind::PRINT(e) => {
+                self.check_expr(e);
+            }
+            StmtKind::VAR(name, type_, initializer) => {
+                let declared = self.annotation(type_.as_deref());
+                let value = match initializer {
+                    Some(e) => self.check_expr(e),
+                    None => Type::Nil,
+                };
+                let target = format!("variable {}", name.lexeme);
+                match initializer {
+                    Some(e) => self.expect(&declared, &value, &target, e.span),
+                    //Without a value it would be nil until the first assignment
+                    None if !declared.accepts(&Type::Nil) => {
+                        self.errors.push(TypeCheckError::Uninitialized(
+                            name.lexeme.clone(),
+                            declared.clone(),
+                            name.span,
+                        ))
+                    }
+                    None => {}
+                }
+                self.define(&name.lexeme, declared);
+            }
+            StmtKind::CONST(name, type_, value) => {
+                let value_type = self.check_expr(value);
+                let type_ = match type_ {
+                    Some(type_) => {
+                        let declared = self.annotation(Some(type_));
+                        let target = format!("constant {}", name.lexeme);
+                        self.expect(&declared, &value_type, &target, value.span);
+                        declared
+                    }
+                    //The value never changes, so its type is known even without annotation
+                    None => value_type,
+                };
+                self.define(&name.lexeme, type_);
+            }
+            StmtKind::BLOCK(stmts) => {
+                self.scopes.push(HashMap::new());
+                for s in stmts.iter() {
+                    self.check_stmt(s);
+                }
+                self.scopes.pop();
+            }
+            StmtKind::IF(condition, then, else_) => {
+                self.check_expr(condition);
+                self.check_stmt(then);
+                if let Some(else_) = else_ {
+                    self.check_stmt(else_);
+                }
+            }
+            StmtKind::WHILE(condition, body) => {
+                self.check_expr(condition);
+                self.check_stmt(body);
+            }
+            StmtKind::FUNCTION(declaration) => {
+                //Defined before the body, so the function can call itself
+                let signature = self.signature(declaration);
+                self.define(&declaration.name.lexeme, Type::Function(signature.clone()));
+                self.check_function(declaration, &signature);
+            }
+            StmtKind::RETURN(keyword, value) => {
+                let (type_, span) = match value {
+                    Some(value) => (self.check_expr(value), value.span),
+                    None => (Type::Nil, keyword.span),
+                };
+                if let Some((name, expected)) = self.current_function.clone() {
+                    let target = format!("return of {}", name);
+                    self.expect(&expected, &type_, &target, span);
+                }
+            }
+            StmtKind::CLASS(name, superclass, methods) => {
+                self.define(&name.lexeme, Type::Any);
+                if let Some(superclass) = superclass {
+                    self.check_expr(superclass);
+                }
+                for method in methods.iter() {
+                    let signature = self.signature(method);
+                    self.check_function(method, &signature);
+                }
+            }
+        }
+    }
+
+    fn check_function(&mut self, declaration: &FunctionDecl, signature: &Signature) {
+        let enclosing = self
+            .current_function
+            .replace((declaration.name.lexeme.clone(), signature.ret.clone()));
+        let mut scope = HashMap::new();
+        for (param, type_) in declaration.params.iter().zip(signature.params.iter()) {
+            scope.insert(param.lexeme.clone(), type_.clone());
+        }
+
+        self.scopes.push(scope);
+        for s in declaration.body.iter() {
+            self.check_stmt(s);
+        }
+        self.scopes.pop();
+        if !declaration.body.iter().any(|s| always_returns(s)) {
+            let target = format!("return of {}", declaration.name.lexeme);
+            self.expect(&signature.ret, &Type::Nil, &target, declaration.close);
+        }
+        self.current_function = enclosing;
+    }
+
+    fn signature(&mut self, declaration: &FunctionDecl) -> Rc<Signature> {
+        let params = declaration
+            .param_types
+            .iter()
+            .map(|type_| self.annotation(type_.as_ref()))
+            .collect();
+        let ret = self.annotation(declaration.return_type.as_ref());
+        Rc::new(Signature { params, ret })
+    }
+
+    fn check_expr(&mut self, expr: &Expr) -> Type {
+        if !self.stack.has_room() {
+            self.too_deep(expr.span);
+            return Type::Any;
+        }
+        match &expr.kind {
+            ExprKind::NumberLit(_) => Type::Number,
+            ExprKind::StringLit(_) => Type::String,
+            ExprKind::Boolean(_) => Type::Boolean,
+            ExprKind::Nil => Type::Nil,
+            ExprKind::Grouping(e) => self.check_expr(e),
+            ExprKind::Unary(operator, right) => {
+                let right = self.check_expr(right);
+                match operator.token_type {
+                    TokenType::MINUS => {
+                        if !Type::Number.accepts(&right) {
+                            self.errors.push(TypeCheckError::InvalidOperands(
+                                format!("Operand of - must be a number, got {}", right),
+                                expr.span,
+                            ));
+                        }
+                        Type::Number
+                    }
+                    _ => Type::Boolean,
+                }
+            }
+            ExprKind::Binary(left, operator, right) => {
+                let left = self.check_expr(left);
+                let right = self.check_expr(right);
+                self.binary(operator, left, right, expr.span)
+            }
+            //and and or return one of the operands, not a boolean
+            ExprKind::Logical(left, _, right) => {
+                let left = self.check_expr(left);
+                left.join(self.check_expr(right))
+            }
+            ExprKind::Conditional(condition, then, else_) => {
+                self.check_expr(condition);
+                let then = self.check_expr(then);
+                then.join(self.check_expr(else_))
+            }
+            ExprKind::Variable(name, _) => self.lookup(name),
+            ExprKind::Assignment(name, value, _) => {
+                let declared = self.assignable(name);
+                let value_type = self.check_expr(value);
+                let target = format!("variable {}", name);
+                self.expect(&declared, &value_type, &target, value.span);
+                value_type
+            }
+            ExprKind::CompoundAssignment(name, operator, value, _) => {
+                let declared = self.assignable(name);
+                let value_type = self.check_expr(value);
+                let result = self.binary(operator, declared.clone(), value_type, expr.span);
+                let target = format!("variable {}", name);
+                self.expect(&declared, &result, &target, expr.span);
+                result
+            }
+            ExprKind::Call(callee, _, arguments) => {
+                let callee = self.check_expr(callee);
+                let arguments: Vec<(Type, Span)> = arguments
+                    .iter()
+                    .map(|a| (self.check_expr(a), a.span))
+                    .collect();
+                let Type::Function(signature) = callee else {
+                    return Type::Any;
+                };
+                //The arity is checked by the interpreter
+                for (i, (expected, (found, span))) in
+                    signature.params.iter().zip(arguments.iter()).enumerate()
+                {
+                    let target = format!("argument {}", i + 1);
+                    self.expect(expected, found, &target, *span);
+                }
+                signature.ret.clone()
+            }
+            ExprKind::Get(object, _) => {
+                self.check_expr(object);
+                Type::Any
+            }
+            ExprKind::Set(object, _, value) | ExprKind::CompoundSet(object, _, _, value) => {
+                self.check_expr(object);
+                self.check_expr(value);
+                Type::Any
+            }
+            ExprKind::This(_, _) | ExprKind::Super(_, _, _) => Type::Any,
+        }
+    }
+
+    //Same rules as the operators of Object. The result of an operation
+    //that fails is still known, so one error is not reported again
+    fn binary(&mut self, operator: &Token, left: Type, right: Type, span: Span) -> Type {
+        let symbol = operator.lexeme.as_str();
+        let message = match operator.token_type {
+            TokenType::MINUS
+            | TokenType::STAR
+            | TokenType::SLASH
+            | TokenType::MINUSEQUAL
+            | TokenType::STAREQUAL
+            | TokenType::SLASHEQUAL => {
+                if Type::Number.accepts(&left) && Type::Number.accepts(&right) {
+                    return Type::Number;
+                }
+                format!("Operands of {} must be numbers", symbol)
+            }
+            TokenType::PLUS | TokenType::PLUSEQUAL => {
+                match (&left, &right) {
+                    (Type::Number | Type::Any, Type::Number) | (Type::Number, Type::Any) => {
+                        return Type::Number
+                    }
+                    (Type::String | Type::Any, Type::String) | (Type::String, Type::Any) => {
+                        return Type::String
+                    }
+                    (Type::Any, Type::Any) => return Type::Any,
+                    _ => {}
+                }
+                format!("Operands of {} must be two numbers or two strings", symbol)
+            }
+            TokenType::GREATER
+            | TokenType::GREATEREQUAL
+            | TokenType::LESS
+            | TokenType::LESSEQUAL => {
+                if left.accepts(&right) {
+                    return Type::Boolean;
+                }
+                format!("Operands of {} must have the same type", symbol)
+            }
+            //== and != work with any pair of values
+            _ => return Type::Boolean,
+        };
+
+        self.errors.push(TypeCheckError::InvalidOperands(
+            format!("{}, got {} and {}", message, left, right),
+            span,
+        ));
+        match operator.token_type {
+            TokenType::GREATER
+            | TokenType::GREATEREQUAL
+            | TokenType::LESS
+            | TokenType::LESSEQUAL => Type::Boolean,
+            _ => Type::Any,
+        }
+    }
+
+    //Reports the value when it does not fit where it is stored
+    //Reported once, every node past that depth fails the same way
+    fn too_deep(&mut self, span: Span) {
+        let reported = self
+            .errors
+            .iter()
+            .any(|e| matches!(e, TypeCheckError::TooDeeplyNested(_)));
+        if !reported {
+            self.errors.push(TypeCheckError::TooDeeplyNested(span));
+        }
+    }
+
+    fn expect(&mut self, expected: &Type, found: &Type, target: &str, span: Span) {
+        if !expected.accepts(found) {
+            self.errors.push(TypeCheckError::Mismatch(
+                target.to_string(),
+                expected.clone(),
+                found.clone(),
+                span,
+            ));
+        }
+    }
+
+    //A missing or unknown annotation is Any. The unknown one is reported
+    fn annotation(&mut self, type_: Option<&Token>) -> Type {
+        let Some(type_) = type_ else {
+            return Type::Any;
+        };
+        match Type::from_name(&type_.lexeme) {
+            Some(t) => t,
+            None => {
+                self.errors.push(TypeCheckError::UnknownType(
+                    type_.lexeme.clone(),
+                    type_.span,
+                ));
+                Type::Any
+            }
+        }
+    }
+
+    fn define(&mut self, name: &str, type_: Type) {
+        match self.scopes.last_mut() {
+            Some(scope) => scope.insert(name.to_string(), type_),
+            None => self.globals.insert(name.to_string(), type_),
+        };
+    }
+
+    //A function declared with fun is a variable without type, it can be
+    //assigned anything. From then on its signature is not known
+    fn assignable(&mut self, name: &str) -> Type {
+        match self.lookup(name) {
+            Type::Function(_) => {
+                let scope = self
+                    .scopes
+                    .iter_mut()
+                    .rev()
+                    .find(|scope| scope.contains_key(name))
+                    .unwrap_or(&mut self.globals);
+                scope.insert(name.to_string(), Type::Any);
+                Type::Any
+            }
+            type_ => type_,
+        }
+    }
+
+    //Variables declared outside the checked code (ie. the natives) are Any
+    fn lookup(&self, name: &str) -> Type {
+        self.scopes
+            .iter()
+            .rev()
+            .find_map(|scope| scope.get(name))
+            .or(self.globals.get(name))
+            .cloned()
+            .unwrap_or(Type::Any)
+    }
+}
+
+//The statement returns on every path. Loops may not run at all, so they never do
+fn always_returns(stmt: &Stmt) -> bool {
+    match &stmt.kind {
+        StmtKind::RETURN(_, _) => true,
+        StmtKind::BLOCK(stmts) => stmts.iter().any(|s| always_returns(s)),
+        StmtKind::IF(_, then, Some(else_)) => always_returns(then) && always_returns(else_),
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod typechecker_tests {
+    use crate::{
+        ast::{parser::Parser, scanner::Scanner},
+        lox::Lox,
+    };
+
+    use super::TypeChecker;
+
+    fn check(src: &str) -> Vec<String> {
+        let src = String::from(src);
+        let mut lox = Lox::default();
+        let mut scanner = Scanner::new(&mut lox, &src);
+        let tokens = scanner.scan_tokens();
+        let stmts = Parser::new(tokens).parse().unwrap();
+        match TypeChecker::new().check(&stmts) {
+            Ok(()) => vec![],
+            Err(errors) => errors.iter().map(|e| e.message()).collect(),
+        }
+    }
+
+    #[test]
+    fn literal_operands() {
+        assert_eq!(
+            check("print \"a\" - 1;"),
+            vec!["Operands of - must be numbers, got string and number"]
+        );
+        assert_eq!(
+            check("print 1 + true;"),
+            vec!["Operands of + must be two numbers or two strings, got number and boolean"]
+        );
+        assert!(check("print \"a\" + \"b\"; print 1 + 2 * 3; print 1 < 2 == true;").is_empty());
+    }
+
+    #[test]
+    fn untyped_code_is_not_checked() {
+        assert!(
+            check("var a = \"a\"; print a - 1; a = 1; fun f(x) { return x; } f = nil;").is_empty()
+        );
+        assert!(check("fun f(a, b) { return a + b; } print f(1, \"b\");").is_empty());
+    }
+
+    #[test]
+    fn annotated_variables() {
+        assert_eq!(
+            check("var a: number = \"s\";"),
+            vec!["Type mismatch in variable a: expected number, got string"]
+        );
+        assert_eq!(
+            check("var a: string = \"s\"; { a = 1; }"),
+            vec!["Type mismatch in variable a: expected string, got number"]
+        );
+        assert_eq!(
+            check("const c = 1; print c + \"s\";"),
+            vec!["Operands of + must be two numbers or two strings, got number and string"]
+        );
+        assert!(check("var a: any = 1; a = \"s\"; var b: nil; var c: number = a;").is_empty());
+    }
+
+    #[test]
+    fn functions() {
+        let src = "fun f(a: number, b): string { return b; } f(\"a\", 1);";
+        assert_eq!(
+            check(src),
+            vec!["Type mismatch in argument 1: expected number, got string"]
+        );
+        assert_eq!(
+            check("fun f(): number { return \"s\"; }"),
+            vec!["Type mismatch in return of f: expected number, got string"]
+        );
+        assert_eq!(
+            check("fun f(): number { return; }"),
+            vec!["Type mismatch in return of f: expected number, got nil"]
+        );
+        assert_eq!(
+            check("fun f(): string { return \"s\"; } print f() - 1;"),
+            vec!["Operands of - must be numbers, got string and number"]
+        );
+        //Ending without a return is a return of nil
+        assert_eq!(
+            check("fun f(a: number): string { if (a > 1) return \"x\"; } print f(0);"),
+            vec!["Type mismatch in return of f: expected string, got nil"]
+        );
+        assert!(check(
+            "fun f(a: number): string { if (a > 1) { return \"x\"; } else return \"y\"; }
+            fun g(): nil {} fun h(): any {} fun i(): string { { return \"s\"; } }"
+        )
+        .is_empty());
+        //Once reassigned, the function may be any other
+        assert!(check("fun f(a: string) {} fun g(b) {} f = g; f(1); { f = g; }").is_empty());
+    }
+
+    #[test]
+    fn annotation_errors() {
+        assert_eq!(
+            check("var a: integer = 1; var b: number;"),
+            vec![
+                "Unknown type integer",
+                "Variable b of type number needs an initial value"
+            ]
+        );
+    }
+}